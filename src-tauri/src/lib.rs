@@ -1,15 +1,159 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tauri::State;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::broadcast::error::RecvError;
 use serde_json::Value;
 use serde::{Deserialize, Serialize};
 use reqwest;
 
 mod mcp;
-use mcp::MCPClient;
+mod schema;
+use mcp::{
+    BoxFuture, ClientHandlers, CreateMessageRequest, CreateMessageResult, ElicitAction, ElicitRequest, ElicitResult,
+    CallToolResult, Completion, CompletionReference, ElicitationHandler, GetPromptResult, Health, ResourceLimit, InitializeResult, LogEntry, MCPClient, Prompt,
+    ReadResourceResult, Resource, ResourceTemplate, Tool,
+    MCPClientOptions, MCPError, MCPNotification, Root, SamplingApproval, SamplingBackend, SharedRoots,
+    SocketEndpoint,
+};
 
-type MCPClients = Arc<Mutex<HashMap<String, MCPClient>>>;
+type MCPClients = Arc<Mutex<HashMap<String, Arc<MCPClient>>>>;
+
+// Server requests handed to the frontend, waiting for the matching respond_* command
+type PendingHostRequests = Arc<Mutex<HashMap<String, oneshot::Sender<Value>>>>;
+
+// How long the frontend has to answer a server request, user interaction included
+const HOST_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+// A server that stayed up this long has its restart count and backoff reset
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+// Longest any one server may hold up app exit; a local server that ignores
+// every signal is killed well within this
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize)]
+struct MCPNotificationEvent {
+    server_name: String,
+    // The tool call a progress notification belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    call_id: Option<String>,
+    #[serde(flatten)]
+    notification: MCPNotification,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum MCPServerStatus {
+    // The process ended on its own, or a restart failed to launch
+    Exited,
+    // Waiting out the backoff before the next restart
+    Restarting,
+    // A restart succeeded and the new process is serving requests
+    Running,
+    // No more restarts; the server has been removed from the connected set
+    Stopped,
+}
+
+// Emitted as mcp:server-status whenever a supervised server changes state
+#[derive(Debug, Serialize)]
+struct MCPServerStatusEvent {
+    server_name: String,
+    status: MCPServerStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    // The last lines the process wrote to stderr
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<String>,
+    // The resource limit the process ran into, when that is what stopped it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<ResourceLimit>,
+    // Which restart in the current run of failures this is, counting from 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempt: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<CommandError>,
+}
+
+// An entry in list_connected_servers
+#[derive(Debug, Serialize)]
+struct ConnectedServer {
+    server_name: String,
+    #[serde(flatten)]
+    health: Health,
+}
+
+// Error returned by every command: `code` says what kind of failure it was so
+// the frontend can react to it, `message` is meant for people
+#[derive(Debug, Serialize)]
+struct CommandError {
+    code: &'static str,
+    message: String,
+    // The JSON-RPC error code when the server answered with an error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rpc_code: Option<i64>,
+    // The JSON-RPC error data, or details such as the exit code and stderr of a dead server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_name: Option<String>,
+}
+
+impl CommandError {
+    fn new(code: &'static str, message: String) -> Self {
+        CommandError {
+            code,
+            message,
+            rpc_code: None,
+            data: None,
+            server_name: None,
+        }
+    }
+
+    fn for_server(mut self, server_name: &str) -> Self {
+        self.server_name = Some(server_name.to_string());
+        self
+    }
+
+    fn from_mcp(server_name: &str, context: &str, error: MCPError) -> Self {
+        let (rpc_code, data) = match error {
+            MCPError::Rpc { code, ref data, .. } => (Some(code), data.clone()),
+            MCPError::Timeout { ref method, timeout } => {
+                (None, Some(serde_json::json!({ "method": method, "timeout_ms": timeout.as_millis() as u64 })))
+            }
+            MCPError::ServerExited { code, ref stderr, limit } => {
+                (None, Some(serde_json::json!({ "exit_code": code, "stderr": stderr, "limit": limit })))
+            }
+            MCPError::Launch { ref stderr, .. } => (None, Some(serde_json::json!({ "stderr": stderr }))),
+            MCPError::InvalidArguments { ref errors, .. } | MCPError::InvalidOutput { ref errors, .. } => {
+                (None, Some(serde_json::json!({ "errors": errors })))
+            }
+            _ => (None, None),
+        };
+
+        CommandError {
+            code: error.code(),
+            message: format!("{}: {}", context, error),
+            rpc_code,
+            data,
+            server_name: Some(server_name.to_string()),
+        }
+    }
+}
+
+// Adapts an MCPError for `map_err`, keeping the server name and what was being done
+fn mcp_error<'a>(server_name: &'a str, context: &'a str) -> impl FnOnce(MCPError) -> CommandError + 'a {
+    move |error| CommandError::from_mcp(server_name, context, error)
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MCPHostRequestEvent<T: Serialize> {
+    request_id: String,
+    server_name: String,
+    #[serde(flatten)]
+    request: T,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct UserProfile {
@@ -49,7 +193,7 @@ struct FileInfo {
 }
 
 #[tauri::command]
-async fn validate_access_key(access_key: String) -> Result<AccessKeyValidationResult, String> {
+async fn validate_access_key(access_key: String) -> Result<AccessKeyValidationResult, CommandError> {
     if access_key.trim().is_empty() {
         return Ok(AccessKeyValidationResult {
             is_valid: false,
@@ -141,16 +285,16 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn read_directory(path: String) -> Result<Vec<FileInfo>, String> {
+fn read_directory(path: String) -> Result<Vec<FileInfo>, CommandError> {
     use std::fs;
     
     let entries = fs::read_dir(&path)
-        .map_err(|e| format!("Failed to read directory: {}", e))?;
+        .map_err(|e| CommandError::new("io_error", format!("Failed to read directory: {}", e)))?;
     
     let mut files = Vec::new();
     
     for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+        let entry = entry.map_err(|e| CommandError::new("io_error", format!("Failed to read entry: {}", e)))?;
         let path = entry.path();
         
         let name = path
@@ -209,47 +353,473 @@ fn read_directory(path: String) -> Result<Vec<FileInfo>, String> {
 }
 
 #[tauri::command]
-fn read_file_content(path: String) -> Result<String, String> {
+fn read_file_content(path: String) -> Result<String, CommandError> {
     use std::fs;
     
     fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read file: {}", e))
+        .map_err(|e| CommandError::new("io_error", format!("Failed to read file: {}", e)))
 }
 
 #[tauri::command]
-fn write_file_content(path: String, content: String) -> Result<(), String> {
+fn write_file_content(path: String, content: String) -> Result<(), CommandError> {
     use std::fs;
     
     fs::write(&path, content)
-        .map_err(|e| format!("Failed to write file: {}", e))
+        .map_err(|e| CommandError::new("io_error", format!("Failed to write file: {}", e)))
+}
+
+// Clone the client out of the map so the lock is not held while a request is in flight
+async fn get_client(clients: &MCPClients, server_name: &str) -> Result<Arc<MCPClient>, CommandError> {
+    let clients_map = clients.lock().await;
+    clients_map
+        .get(server_name)
+        .cloned()
+        .ok_or_else(|| not_connected(server_name))
+}
+
+fn not_connected(server_name: &str) -> CommandError {
+    CommandError::new("not_connected", format!("Server {} not connected", server_name)).for_server(server_name)
+}
+
+fn already_connected(server_name: &str) -> CommandError {
+    CommandError::new("already_connected", format!("Server {} is already connected", server_name)).for_server(server_name)
+}
+
+// Adds a newly connected client under `server_name`. The handshake runs
+// without the lock, so another connect may have taken the name meanwhile;
+// the new client is then shut down instead.
+async fn insert_client(clients: &MCPClients, server_name: &str, client: MCPClient) -> Result<Arc<MCPClient>, CommandError> {
+    let client = Arc::new(client);
+    let mut clients_map = clients.lock().await;
+    if clients_map.contains_key(server_name) {
+        drop(clients_map);
+        let _ = client.shutdown().await;
+        return Err(already_connected(server_name));
+    }
+    clients_map.insert(server_name.to_string(), client.clone());
+    Ok(client)
+}
+
+// Re-emit every notification from a server as a Tauri event tagged with the server name
+fn forward_notifications(app: AppHandle, server_name: String, client: &MCPClient) {
+    let mut receiver = client.subscribe_notifications();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(notification) => {
+                    // call_mcp_tool uses the call id as the progress token
+                    let call_id = match notification {
+                        MCPNotification::Progress { ref progress_token, .. } => progress_token.as_str().map(String::from),
+                        _ => None,
+                    };
+                    let event = MCPNotificationEvent {
+                        server_name: server_name.clone(),
+                        call_id,
+                        notification,
+                    };
+                    if let Err(e) = app.emit(event.notification.event_name(), &event) {
+                        println!("[ERROR] Failed to emit notification from {}: {}", server_name, e);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    println!("[DEBUG] Dropped {} notifications from {}", skipped, server_name);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+// Takes a request back from the frontend unless it was answered: it timed
+// out, or the server request was withdrawn because its tool call ended.
+// Emits mcp:host-request-withdrawn so the UI can close its prompt.
+struct WithdrawUnanswered {
+    app: AppHandle,
+    pending: PendingHostRequests,
+    request_id: String,
+    server_name: String,
+    answered: bool,
+}
+
+impl Drop for WithdrawUnanswered {
+    fn drop(&mut self) {
+        if self.answered {
+            return;
+        }
+        let (app, pending) = (self.app.clone(), self.pending.clone());
+        let payload = serde_json::json!({ "request_id": self.request_id, "server_name": self.server_name });
+        tauri::async_runtime::spawn(async move {
+            if let Some(request_id) = payload["request_id"].as_str() {
+                pending.lock().await.remove(request_id);
+            }
+            if let Err(e) = app.emit("mcp:host-request-withdrawn", &payload) {
+                println!("[ERROR] Failed to emit withdrawal of {}: {}", payload["request_id"], e);
+            }
+        });
+    }
+}
+
+// Emits a server request to the frontend and waits for its answer
+async fn ask_frontend<T: Serialize + Clone>(
+    app: &AppHandle,
+    pending: &PendingHostRequests,
+    event: &str,
+    server_name: &str,
+    request: T,
+) -> Result<Value, String> {
+    let request_id = uuid::Uuid::new_v4().to_string();
+    let (sender, receiver) = oneshot::channel();
+    pending.lock().await.insert(request_id.clone(), sender);
+    let mut withdraw = WithdrawUnanswered {
+        app: app.clone(),
+        pending: pending.clone(),
+        request_id: request_id.clone(),
+        server_name: server_name.to_string(),
+        answered: false,
+    };
+
+    let payload = MCPHostRequestEvent {
+        request_id,
+        server_name: server_name.to_string(),
+        request,
+    };
+    if let Err(e) = app.emit(event, &payload) {
+        return Err(format!("Failed to emit {}: {}", event, e));
+    }
+
+    match tokio::time::timeout(HOST_REQUEST_TIMEOUT, receiver).await {
+        Ok(Ok(response)) => {
+            withdraw.answered = true;
+            Ok(response)
+        }
+        Ok(Err(_)) => Err("Request was dropped".to_string()),
+        Err(_) => Err(format!("No response to {} within {}s", event, HOST_REQUEST_TIMEOUT.as_secs())),
+    }
+}
+
+async fn resolve_host_request(pending: &PendingHostRequests, request_id: &str, response: Value) -> Result<(), CommandError> {
+    let sender = pending.lock().await.remove(request_id)
+        .ok_or_else(|| CommandError::new("invalid_request", format!("No pending request with id {}", request_id)))?;
+    sender.send(response)
+        .map_err(|_| CommandError::new("invalid_request", format!("Request {} is no longer waiting", request_id)))
+}
+
+// Server requests answered by the frontend, which asks the user and runs the app's model
+struct FrontendBridge {
+    app: AppHandle,
+    server_name: String,
+    pending: PendingHostRequests,
+}
+
+impl SamplingApproval for FrontendBridge {
+    fn approve(&self, request: CreateMessageRequest) -> BoxFuture<bool> {
+        let (app, server_name, pending) = (self.app.clone(), self.server_name.clone(), self.pending.clone());
+        Box::pin(async move {
+            match ask_frontend(&app, &pending, "mcp:sampling-approval", &server_name, request).await {
+                Ok(response) => response.as_bool().unwrap_or(false),
+                Err(e) => {
+                    println!("[ERROR] Sampling approval for {} failed: {}", server_name, e);
+                    false
+                }
+            }
+        })
+    }
+}
+
+impl SamplingBackend for FrontendBridge {
+    fn create_message(&self, request: CreateMessageRequest) -> BoxFuture<Result<CreateMessageResult, String>> {
+        let (app, server_name, pending) = (self.app.clone(), self.server_name.clone(), self.pending.clone());
+        Box::pin(async move {
+            let response = ask_frontend(&app, &pending, "mcp:sampling-request", &server_name, request).await?;
+            if let Some(error) = response.get("error").and_then(Value::as_str) {
+                return Err(error.to_string());
+            }
+            serde_json::from_value(response["result"].clone())
+                .map_err(|e| format!("Invalid sampling result: {}", e))
+        })
+    }
+}
+
+impl ElicitationHandler for FrontendBridge {
+    fn elicit(&self, request: ElicitRequest) -> BoxFuture<Result<ElicitResult, String>> {
+        let (app, server_name, pending) = (self.app.clone(), self.server_name.clone(), self.pending.clone());
+        Box::pin(async move {
+            match ask_frontend(&app, &pending, "mcp:elicitation-request", &server_name, request).await {
+                Ok(response) => serde_json::from_value(response)
+                    .map_err(|e| format!("Invalid elicitation response: {}", e)),
+                // Nobody answered in time; the server should not keep waiting
+                Err(e) => {
+                    println!("[ERROR] Elicitation for {} failed: {}", server_name, e);
+                    Ok(ElicitResult { action: ElicitAction::Cancel, content: None })
+                }
+            }
+        })
+    }
+}
+
+// What the supervisor needs to start a local server again
+struct ServerLaunch {
+    command: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    options: MCPClientOptions,
+}
+
+fn emit_status(app: &AppHandle, event: MCPServerStatusEvent) {
+    if let Err(e) = app.emit("mcp:server-status", &event) {
+        println!("[ERROR] Failed to emit status of {}: {}", event.server_name, e);
+    }
+}
+
+// Whether `client` is still the one connected under `server_name`; a
+// disconnect or reconnect means its exit was intended
+async fn is_current(clients: &MCPClients, server_name: &str, client: &Arc<MCPClient>) -> bool {
+    clients.lock().await.get(server_name).is_some_and(|current| Arc::ptr_eq(current, client))
+}
+
+// Watches a local server and, when it exits on its own, reports the exit and
+// restarts it according to its restart policy
+fn supervise(app: AppHandle, clients: MCPClients, server_name: String, client: Arc<MCPClient>, launch: ServerLaunch) {
+    let restart = launch.options.restart.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut client = client;
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let exit = client.wait_for_exit().await;
+            if !is_current(&clients, &server_name, &client).await {
+                return;
+            }
+
+            println!("[MCP] Server {} stopped unexpectedly: {}", server_name, exit);
+            // Reaps anything the dead server left running before a new one starts
+            let _ = client.shutdown().await;
+            let (exit_code, stderr, limit) = match exit {
+                MCPError::ServerExited { code, ref stderr, limit } => {
+                    (code, Some(stderr.clone()).filter(|s| !s.is_empty()), limit)
+                }
+                _ => (None, None, None),
+            };
+            emit_status(&app, MCPServerStatusEvent {
+                server_name: server_name.clone(),
+                status: MCPServerStatus::Exited,
+                exit_code,
+                stderr,
+                limit,
+                attempt: None,
+                error: None,
+            });
+
+            if started.elapsed() >= STABLE_UPTIME {
+                attempt = 0;
+            }
+
+            let should_restart = restart.should_restart(&exit);
+            let restarted = loop {
+                if !should_restart || attempt >= restart.max_retries {
+                    break None;
+                }
+                attempt += 1;
+                emit_status(&app, MCPServerStatusEvent {
+                    server_name: server_name.clone(),
+                    status: MCPServerStatus::Restarting,
+                    exit_code: None,
+                    stderr: None,
+                    limit: None,
+                    attempt: Some(attempt),
+                    error: None,
+                });
+                tokio::time::sleep(restart.backoff(attempt)).await;
+                if !is_current(&clients, &server_name, &client).await {
+                    return;
+                }
+
+                println!("[MCP] Restarting {} (attempt {})", server_name, attempt);
+                let launched = MCPClient::new_with_env(
+                    launch.command.clone(),
+                    launch.args.clone(),
+                    launch.env.clone(),
+                    launch.options.clone(),
+                )
+                .await;
+                match launched {
+                    Ok(new_client) => break Some(new_client),
+                    Err(e) => {
+                        println!("[ERROR] Failed to restart {}: {}", server_name, e);
+                        let (exit_code, limit) = match e {
+                            MCPError::ServerExited { code, limit, .. } => (code, limit),
+                            _ => (None, None),
+                        };
+                        emit_status(&app, MCPServerStatusEvent {
+                            server_name: server_name.clone(),
+                            status: MCPServerStatus::Exited,
+                            exit_code,
+                            stderr: None,
+                            limit,
+                            attempt: Some(attempt),
+                            error: Some(CommandError::from_mcp(&server_name, &format!("Failed to restart {}", server_name), e)),
+                        });
+                    }
+                }
+            };
+
+            let mut clients_map = clients.lock().await;
+            let current = clients_map.get(&server_name).is_some_and(|current| Arc::ptr_eq(current, &client));
+            match restarted {
+                Some(new_client) if current => {
+                    forward_notifications(app.clone(), server_name.clone(), &new_client);
+                    client = Arc::new(new_client);
+                    client.start_health_checks();
+                    clients_map.insert(server_name.clone(), client.clone());
+                    drop(clients_map);
+                    emit_status(&app, MCPServerStatusEvent {
+                        server_name: server_name.clone(),
+                        status: MCPServerStatus::Running,
+                        exit_code: None,
+                        stderr: None,
+                        limit: None,
+                        attempt: Some(attempt),
+                        error: None,
+                    });
+                }
+                // Disconnected while the restart was launching
+                Some(new_client) => {
+                    drop(clients_map);
+                    let _ = new_client.shutdown().await;
+                    return;
+                }
+                None => {
+                    if current {
+                        clients_map.remove(&server_name);
+                    }
+                    drop(clients_map);
+                    emit_status(&app, MCPServerStatusEvent {
+                        server_name: server_name.clone(),
+                        status: MCPServerStatus::Stopped,
+                        exit_code: None,
+                        stderr: None,
+                        limit: None,
+                        attempt: None,
+                        error: None,
+                    });
+                    return;
+                }
+            }
+        }
+    });
+}
+
+// Installs the frontend-backed handlers for the features the caller enabled
+fn host_handlers(
+    app: &AppHandle,
+    server_name: &str,
+    pending: &PendingHostRequests,
+    roots: &SharedRoots,
+    options: &MCPClientOptions,
+) -> ClientHandlers {
+    let mut handlers = ClientHandlers::default();
+    if options.roots {
+        handlers.roots = Some(roots.clone());
+    }
+    let bridge = Arc::new(FrontendBridge {
+        app: app.clone(),
+        server_name: server_name.to_string(),
+        pending: pending.clone(),
+    });
+    if options.sampling {
+        handlers.sampling = Some(bridge.clone());
+        handlers.sampling_approval = Some(bridge.clone());
+    }
+    if options.elicitation {
+        handlers.elicitation = Some(bridge);
+    }
+    handlers
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn connect_mcp_server(
     server_name: String,
     command: String,
     args: Vec<String>,
     env: HashMap<String, String>,
+    options: Option<MCPClientOptions>,
+    app: AppHandle,
     clients: State<'_, MCPClients>,
-) -> Result<String, String> {
+    host_requests: State<'_, PendingHostRequests>,
+    roots: State<'_, SharedRoots>,
+) -> Result<String, CommandError> {
     println!("[DEBUG] connect_mcp_server called for {}", server_name);
     println!("[DEBUG] Command: {} {:?}", command, args);
     println!("[DEBUG] Environment variables: {:?}", env.keys().collect::<Vec<_>>());
     
-    let mut clients_map = clients.lock().await;
-    
-    if clients_map.contains_key(&server_name) {
-        return Err(format!("Server {} is already connected", server_name));
+    // Not held while connecting, so other servers stay usable meanwhile
+    if clients.lock().await.contains_key(&server_name) {
+        return Err(already_connected(&server_name));
     }
 
-    match MCPClient::new_with_env(command.clone(), args.clone(), env).await {
+    // A tcp:// or unix:// command points at a server that is already running
+    let mut options = options.unwrap_or_default();
+    options.handlers = host_handlers(&app, &server_name, &host_requests, &roots, &options);
+    let (connection, launch) = match SocketEndpoint::parse(&command) {
+        Some(endpoint) => (MCPClient::new_socket(endpoint, options).await, None),
+        None => {
+            let connection = MCPClient::new_with_env(command.clone(), args.clone(), env.clone(), options.clone()).await;
+            (connection, Some(ServerLaunch { command, args, env, options }))
+        }
+    };
+
+    match connection {
         Ok(client) => {
-            clients_map.insert(server_name.clone(), client);
+            let client = insert_client(&clients, &server_name, client).await?;
+            forward_notifications(app.clone(), server_name.clone(), &client);
+            client.start_health_checks();
+            if let Some(launch) = launch {
+                supervise(app, clients.inner().clone(), server_name.clone(), client, launch);
+            }
             Ok(format!("Connected to MCP server with environment: {}", server_name))
         },
         Err(e) => {
             println!("[ERROR] Failed to connect {}: {}", server_name, e);
-            Err(format!("Failed to connect to {}: {}", server_name, e))
+            Err(CommandError::from_mcp(&server_name, &format!("Failed to connect to {}", server_name), e))
+        }
+    }
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn connect_mcp_server_http(
+    server_name: String,
+    url: String,
+    headers: HashMap<String, String>,
+    options: Option<MCPClientOptions>,
+    app: AppHandle,
+    clients: State<'_, MCPClients>,
+    host_requests: State<'_, PendingHostRequests>,
+    roots: State<'_, SharedRoots>,
+) -> Result<String, CommandError> {
+    println!("[DEBUG] connect_mcp_server_http called for {}", server_name);
+    println!("[DEBUG] URL: {}", url);
+    println!("[DEBUG] Headers: {:?}", headers.keys().collect::<Vec<_>>());
+
+    // Not held while connecting; the handshake may wait out a timeout and a fallback
+    if clients.lock().await.contains_key(&server_name) {
+        return Err(already_connected(&server_name));
+    }
+
+    let mut options = options.unwrap_or_default();
+    options.handlers = host_handlers(&app, &server_name, &host_requests, &roots, &options);
+
+    match MCPClient::new_http(url, headers, options).await {
+        Ok(client) => {
+            let client = insert_client(&clients, &server_name, client).await?;
+            forward_notifications(app, server_name.clone(), &client);
+            client.start_health_checks();
+            Ok(format!("Connected to remote MCP server: {}", server_name))
+        },
+        Err(e) => {
+            println!("[ERROR] Failed to connect {}: {}", server_name, e);
+            Err(CommandError::from_mcp(&server_name, &format!("Failed to connect to {}", server_name), e))
         }
     }
 }
@@ -258,15 +828,15 @@ async fn connect_mcp_server(
 async fn disconnect_mcp_server(
     server_name: String,
     clients: State<'_, MCPClients>,
-) -> Result<String, String> {
-    let mut clients_map = clients.lock().await;
+) -> Result<String, CommandError> {
+    let client = clients.lock().await.remove(&server_name);
     
-    if let Some(mut client) = clients_map.remove(&server_name) {
+    if let Some(client) = client {
         client.shutdown().await
-            .map_err(|e| format!("Failed to shutdown {}: {}", server_name, e))?;
+            .map_err(|e| CommandError::from_mcp(&server_name, &format!("Failed to shutdown {}", server_name), e))?;
         Ok(format!("Disconnected MCP server: {}", server_name))
     } else {
-        Err(format!("Server {} not connected", server_name))
+        Err(not_connected(&server_name))
     }
 }
 
@@ -274,15 +844,10 @@ async fn disconnect_mcp_server(
 async fn list_mcp_tools(
     server_name: String,
     clients: State<'_, MCPClients>,
-) -> Result<Value, String> {
-    let mut clients_map = clients.lock().await;
-    
-    if let Some(client) = clients_map.get_mut(&server_name) {
-        client.list_tools().await
-            .map_err(|e| format!("Failed to list tools: {}", e))
-    } else {
-        Err(format!("Server {} not connected", server_name))
-    }
+) -> Result<Vec<Tool>, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.list_tools().await
+        .map_err(mcp_error(&server_name, "Failed to list tools"))
 }
 
 #[tauri::command]
@@ -290,31 +855,39 @@ async fn call_mcp_tool(
     server_name: String,
     tool_name: String,
     arguments: Value,
+    timeout_ms: Option<u64>,
+    call_id: Option<String>,
     clients: State<'_, MCPClients>,
-) -> Result<Value, String> {
-    let mut clients_map = clients.lock().await;
-    
-    if let Some(client) = clients_map.get_mut(&server_name) {
-        client.call_tool(&tool_name, arguments).await
-            .map_err(|e| format!("Failed to call tool: {}", e))
-    } else {
-        Err(format!("Server {} not connected", server_name))
-    }
+) -> Result<CallToolResult, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    let timeout = timeout_ms.map(std::time::Duration::from_millis);
+    // Every call gets an id so the server can report progress against it
+    let call_id = call_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    client.call_tool(&tool_name, arguments, timeout, Some(&call_id)).await
+        .map_err(mcp_error(&server_name, "Failed to call tool"))
+}
+
+#[tauri::command]
+async fn cancel_mcp_tool_call(
+    server_name: String,
+    call_id: String,
+    reason: Option<String>,
+    clients: State<'_, MCPClients>,
+) -> Result<String, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.cancel_call(&call_id, reason).await
+        .map_err(mcp_error(&server_name, "Failed to cancel tool call"))?;
+    Ok(format!("Cancelled tool call {} on {}", call_id, server_name))
 }
 
 #[tauri::command]
 async fn list_mcp_resources(
     server_name: String,
     clients: State<'_, MCPClients>,
-) -> Result<Value, String> {
-    let mut clients_map = clients.lock().await;
-    
-    if let Some(client) = clients_map.get_mut(&server_name) {
-        client.list_resources().await
-            .map_err(|e| format!("Failed to list resources: {}", e))
-    } else {
-        Err(format!("Server {} not connected", server_name))
-    }
+) -> Result<Vec<Resource>, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.list_resources().await
+        .map_err(mcp_error(&server_name, "Failed to list resources"))
 }
 
 #[tauri::command]
@@ -322,23 +895,199 @@ async fn read_mcp_resource(
     server_name: String,
     uri: String,
     clients: State<'_, MCPClients>,
-) -> Result<Value, String> {
-    let mut clients_map = clients.lock().await;
-    
-    if let Some(client) = clients_map.get_mut(&server_name) {
-        client.read_resource(&uri).await
-            .map_err(|e| format!("Failed to read resource: {}", e))
-    } else {
-        Err(format!("Server {} not connected", server_name))
+) -> Result<ReadResourceResult, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.read_resource(&uri).await
+        .map_err(mcp_error(&server_name, "Failed to read resource"))
+}
+
+#[tauri::command]
+async fn list_mcp_resource_templates(
+    server_name: String,
+    clients: State<'_, MCPClients>,
+) -> Result<Vec<ResourceTemplate>, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.list_resource_templates().await
+        .map_err(mcp_error(&server_name, "Failed to list resource templates"))
+}
+
+#[tauri::command]
+async fn subscribe_mcp_resource(
+    server_name: String,
+    uri: String,
+    clients: State<'_, MCPClients>,
+) -> Result<(), CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.subscribe_resource(&uri).await
+        .map_err(mcp_error(&server_name, "Failed to subscribe to resource"))
+}
+
+#[tauri::command]
+async fn unsubscribe_mcp_resource(
+    server_name: String,
+    uri: String,
+    clients: State<'_, MCPClients>,
+) -> Result<(), CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.unsubscribe_resource(&uri).await
+        .map_err(mcp_error(&server_name, "Failed to unsubscribe from resource"))
+}
+
+#[tauri::command]
+async fn list_mcp_prompts(
+    server_name: String,
+    clients: State<'_, MCPClients>,
+) -> Result<Vec<Prompt>, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.list_prompts().await
+        .map_err(mcp_error(&server_name, "Failed to list prompts"))
+}
+
+#[tauri::command]
+async fn get_mcp_prompt(
+    server_name: String,
+    prompt_name: String,
+    arguments: Option<HashMap<String, String>>,
+    clients: State<'_, MCPClients>,
+) -> Result<GetPromptResult, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.get_prompt(&prompt_name, arguments.unwrap_or_default()).await
+        .map_err(mcp_error(&server_name, "Failed to get prompt"))
+}
+
+#[tauri::command]
+async fn complete_mcp_argument(
+    server_name: String,
+    reference: CompletionReference,
+    argument_name: String,
+    value: String,
+    context: Option<HashMap<String, String>>,
+    clients: State<'_, MCPClients>,
+) -> Result<Completion, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.complete(reference, &argument_name, &value, context.unwrap_or_default()).await
+        .map_err(mcp_error(&server_name, "Failed to complete argument"))
+}
+
+#[tauri::command]
+async fn get_mcp_server_info(
+    server_name: String,
+    clients: State<'_, MCPClients>,
+) -> Result<InitializeResult, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.server_info()
+        .ok_or_else(|| CommandError::from_mcp(&server_name, "Failed to get server info", MCPError::NotInitialized))
+}
+
+#[tauri::command]
+async fn get_mcp_server_logs(
+    server_name: String,
+    limit: Option<usize>,
+    clients: State<'_, MCPClients>,
+) -> Result<Vec<LogEntry>, CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    Ok(client.logs(limit))
+}
+
+#[tauri::command]
+async fn set_mcp_log_level(
+    server_name: String,
+    level: String,
+    clients: State<'_, MCPClients>,
+) -> Result<(), CommandError> {
+    let client = get_client(&clients, &server_name).await?;
+    client.set_log_level(&level).await
+        .map_err(mcp_error(&server_name, "Failed to set log level"))
+}
+
+#[tauri::command]
+async fn respond_mcp_sampling_approval(
+    request_id: String,
+    approved: bool,
+    host_requests: State<'_, PendingHostRequests>,
+) -> Result<(), CommandError> {
+    resolve_host_request(&host_requests, &request_id, Value::Bool(approved)).await
+}
+
+#[tauri::command]
+async fn respond_mcp_sampling(
+    request_id: String,
+    result: Option<CreateMessageResult>,
+    error: Option<String>,
+    host_requests: State<'_, PendingHostRequests>,
+) -> Result<(), CommandError> {
+    let response = match (result, error) {
+        (Some(result), _) => serde_json::json!({ "result": result }),
+        (None, error) => serde_json::json!({ "error": error.unwrap_or_else(|| "No result".to_string()) }),
+    };
+    resolve_host_request(&host_requests, &request_id, response).await
+}
+
+#[tauri::command]
+async fn respond_mcp_elicitation(
+    request_id: String,
+    action: ElicitAction,
+    content: Option<Value>,
+    host_requests: State<'_, PendingHostRequests>,
+) -> Result<(), CommandError> {
+    if action == ElicitAction::Accept && content.is_none() {
+        return Err(CommandError::new("invalid_request", "Accepting an elicitation requires content".to_string()));
     }
+    let content = if action == ElicitAction::Accept { content } else { None };
+
+    let response = serde_json::json!(ElicitResult { action, content });
+    resolve_host_request(&host_requests, &request_id, response).await
+}
+
+// Replaces the workspace roots and tells the connected servers they are shared with
+#[tauri::command]
+async fn set_mcp_workspace_roots(
+    paths: Vec<String>,
+    clients: State<'_, MCPClients>,
+    roots: State<'_, SharedRoots>,
+) -> Result<(), CommandError> {
+    let mut updated = Vec::new();
+    for path in paths {
+        let uri = reqwest::Url::from_file_path(&path)
+            .map_err(|_| CommandError::new("invalid_request", format!("Workspace root must be an absolute path: {}", path)))?;
+        let name = std::path::Path::new(&path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+        updated.push(Root { uri: uri.to_string(), name });
+    }
+
+    {
+        let mut current = roots.write().unwrap();
+        if *current == updated {
+            return Ok(());
+        }
+        *current = updated;
+    }
+
+    let connected: Vec<(String, Arc<MCPClient>)> = clients.lock().await
+        .iter()
+        .map(|(name, client)| (name.clone(), client.clone()))
+        .collect();
+    for (server_name, client) in connected {
+        if let Err(e) = client.notify_roots_changed().await {
+            println!("[ERROR] Failed to notify {} of new roots: {}", server_name, e);
+        }
+    }
+    Ok(())
 }
 
 #[tauri::command]
 async fn list_connected_servers(
     clients: State<'_, MCPClients>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<ConnectedServer>, CommandError> {
     let clients_map = clients.lock().await;
-    Ok(clients_map.keys().cloned().collect())
+    Ok(clients_map
+        .iter()
+        .map(|(server_name, client)| ConnectedServer {
+            server_name: server_name.clone(),
+            health: client.health(),
+        })
+        .collect())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -350,6 +1099,8 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .manage(MCPClients::default())
+        .manage(PendingHostRequests::default())
+        .manage(SharedRoots::default())
         .invoke_handler(tauri::generate_handler![
             greet,
             validate_access_key,
@@ -357,13 +1108,51 @@ pub fn run() {
             read_file_content, 
             write_file_content,
             connect_mcp_server,
+            connect_mcp_server_http,
             disconnect_mcp_server,
             list_mcp_tools,
             call_mcp_tool,
+            cancel_mcp_tool_call,
             list_mcp_resources,
             read_mcp_resource,
+            list_mcp_resource_templates,
+            subscribe_mcp_resource,
+            unsubscribe_mcp_resource,
+            list_mcp_prompts,
+            get_mcp_prompt,
+            complete_mcp_argument,
+            get_mcp_server_info,
+            get_mcp_server_logs,
+            set_mcp_log_level,
+            respond_mcp_sampling_approval,
+            respond_mcp_sampling,
+            respond_mcp_elicitation,
+            set_mcp_workspace_roots,
             list_connected_servers
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let clients = app.state::<MCPClients>().inner().clone();
+                tauri::async_runtime::block_on(shutdown_all(clients));
+            }
+        });
+}
+
+// Stops every connected server before the app exits, so none are left orphaned
+async fn shutdown_all(clients: MCPClients) {
+    let clients: Vec<(String, Arc<MCPClient>)> = clients.lock().await.drain().collect();
+    let mut shutdowns = tokio::task::JoinSet::new();
+    for (server_name, client) in clients {
+        shutdowns.spawn(async move {
+            println!("[MCP] Shutting down {}", server_name);
+            match tokio::time::timeout(SHUTDOWN_TIMEOUT, client.shutdown()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => println!("[ERROR] Failed to shut down {}: {}", server_name, e),
+                Err(_) => println!("[ERROR] Gave up waiting for {} to shut down", server_name),
+            }
+        });
+    }
+    while shutdowns.join_next().await.is_some() {}
 }
@@ -1,238 +1,3381 @@
-use std::collections::HashMap;
-// use std::sync::Arc;
-// use tokio::sync::Mutex;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, Command, ChildStdin, ChildStdout};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Mutex, Notify, Semaphore};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command, ChildStdin};
+use tokio::task::JoinHandle;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use crate::schema::{self, ValidationError};
+
+const NOTIFICATION_CHANNEL_CAPACITY: usize = 256;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// Progress and pending user prompts push a tool call's deadline back, so long
+// calls that report in are not cut off
+const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(60);
+const SESSION_ID_HEADER: &str = "mcp-session-id";
+const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+const LOG_BUFFER_CAPACITY: usize = 500;
+const STDERR_TAIL_LINES: usize = 20;
+// How long to wait for a dead server's last stderr output and exit status before reporting
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+const DEFAULT_LOG_LEVEL: &str = "info";
+// How long a local server gets to exit after stdin closes, and again after
+// SIGTERM; also how long a remote server gets to acknowledge a closed session
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(2);
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// A ping slower than this marks the server degraded even though it answered
+const SLOW_PING_LATENCY: Duration = Duration::from_secs(2);
+// Failed pings in a row before a degraded server counts as unresponsive
+const UNRESPONSIVE_AFTER_FAILURES: u32 = 3;
+
+// Severities accepted by `logging/setLevel`, least severe first
+const LOG_LEVELS: &[&str] = &["debug", "info", "notice", "warning", "error", "critical", "alert", "emergency"];
+
+// JSON-RPC error codes used when answering server requests
+const USER_REJECTED: i64 = -1;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+// Spec revisions this client speaks, newest first; the first is requested
+const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Per-server settings supplied when connecting.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MCPClientOptions {
+    /// Deadline for requests other than tool calls, in milliseconds;
+    /// defaults to 60 seconds.
+    pub request_timeout_ms: Option<u64>,
+    /// Default deadline for tool calls, in milliseconds; defaults to 60
+    /// seconds, and 0 lets calls wait as long as the server takes.
+    pub tool_timeout_ms: Option<u64>,
+    /// Let the server request completions from the app's model.
+    #[serde(default)]
+    pub sampling: bool,
+    /// Let the server ask the user for input mid-request.
+    #[serde(default)]
+    pub elicitation: bool,
+    /// Share the workspace roots with the server through `roots/list`.
+    #[serde(default)]
+    pub roots: bool,
+    /// Minimum severity of log messages requested from servers that support
+    /// logging; defaults to `info`.
+    pub log_level: Option<String>,
+    /// Check `structuredContent` in tool results against the tool's
+    /// `outputSchema`, failing the call when it does not match.
+    #[serde(default)]
+    pub validate_output: bool,
+    /// What to do when a local server process exits on its own.
+    #[serde(default)]
+    pub restart: RestartOptions,
+    /// How often to ping the server to check it still answers, in
+    /// milliseconds; defaults to 30 seconds, and 0 turns checks off.
+    pub health_check_interval_ms: Option<u64>,
+    /// Caps on what a local server process may use.
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Host-side handlers for requests the server sends to the client.
+    #[serde(skip)]
+    pub handlers: ClientHandlers,
+}
+
+/// Whether a local server that exits on its own is started again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// Restart unless the process exited with status 0.
+    OnFailure,
+    Always,
+}
+
+/// How a local server is restarted after it exits.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RestartOptions {
+    pub policy: RestartPolicy,
+    /// Restarts attempted in a row before giving up.
+    pub max_retries: u32,
+    /// Delay before the first restart, doubled for each one after it.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RestartOptions {
+    fn default() -> Self {
+        RestartOptions {
+            policy: RestartPolicy::Never,
+            max_retries: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl RestartOptions {
+    /// Whether a server whose connection ended with `exit` should be started again.
+    pub fn should_restart(&self, exit: &MCPError) -> bool {
+        match self.policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !matches!(exit, MCPError::ServerExited { code: Some(0), .. }),
+            RestartPolicy::Always => true,
+        }
+    }
+
+    /// How long to wait before restart number `attempt`, counting from 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(self.initial_backoff_ms.saturating_mul(factor).min(self.max_backoff_ms))
+    }
+}
+
+/// Per-server resource caps. The process limits are applied when a local
+/// server is spawned on Linux and ignored elsewhere.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceLimits {
+    /// Address space the process may map (RLIMIT_AS), in bytes.
+    pub max_memory_bytes: Option<u64>,
+    /// CPU time the process may use (RLIMIT_CPU), in seconds.
+    pub max_cpu_seconds: Option<u64>,
+    /// File descriptors the process may hold open (RLIMIT_NOFILE).
+    pub max_open_files: Option<u64>,
+    /// Tool calls allowed in flight at once; further calls wait for a slot.
+    /// Zero is rejected, since no call could ever run.
+    pub max_concurrent_calls: Option<NonZeroUsize>,
+}
+
+/// A resource limit a server process ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceLimit {
+    Memory,
+    CpuTime,
+    OpenFiles,
+}
+
+impl std::fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceLimit::Memory => write!(f, "memory"),
+            ResourceLimit::CpuTime => write!(f, "CPU time"),
+            ResourceLimit::OpenFiles => write!(f, "open files"),
+        }
+    }
+}
+
+impl ResourceLimits {
+    fn has_process_limits(&self) -> bool {
+        self.max_memory_bytes.is_some() || self.max_cpu_seconds.is_some() || self.max_open_files.is_some()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn apply(&self, cmd: &mut Command) {
+        // Soft and hard limits. At the hard CPU limit the kernel sends SIGKILL,
+        // which says nothing about why; a second's headroom means the soft
+        // limit's SIGXCPU ends the process first
+        let limits: Vec<_> = [
+            (libc::RLIMIT_AS, self.max_memory_bytes.map(|bytes| (bytes, bytes))),
+            (libc::RLIMIT_CPU, self.max_cpu_seconds.map(|seconds| (seconds, seconds.saturating_add(1)))),
+            (libc::RLIMIT_NOFILE, self.max_open_files.map(|files| (files, files))),
+        ]
+        .into_iter()
+        .filter_map(|(resource, value)| value.map(|(soft, hard)| (resource, soft as libc::rlim_t, hard as libc::rlim_t)))
+        .collect();
+        if limits.is_empty() {
+            return;
+        }
+
+        // SAFETY: the closure runs in the child between fork and exec and only
+        // calls setrlimit, which is async-signal-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                for &(resource, soft, hard) in &limits {
+                    let limit = libc::rlimit { rlim_cur: soft, rlim_max: hard };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply(&self, _cmd: &mut Command) {
+        if self.has_process_limits() {
+            println!("[MCP] Resource limits are only applied on Linux; ignoring them");
+        }
+    }
+
+    // Works out which limit a dead process ran into. The kernel signals a CPU
+    // overrun; running out of memory or descriptors only shows up as the
+    // errors the server printed before giving up.
+    #[cfg(target_os = "linux")]
+    fn exceeded(&self, exit: Option<ProcessExit>, stderr: &str) -> Option<ResourceLimit> {
+        if !self.has_process_limits() {
+            return None;
+        }
+        if self.max_cpu_seconds.is_some() && exit.and_then(|exit| exit.signal) == Some(libc::SIGXCPU) {
+            return Some(ResourceLimit::CpuTime);
+        }
+
+        let stderr = stderr.to_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|needle| stderr.contains(needle));
+        if self.max_memory_bytes.is_some()
+            && mentions(&["out of memory", "memoryerror", "cannot allocate memory", "enomem", "bad_alloc", "allocation failed"])
+        {
+            return Some(ResourceLimit::Memory);
+        }
+        if self.max_open_files.is_some() && mentions(&["too many open files", "emfile"]) {
+            return Some(ResourceLimit::OpenFiles);
+        }
+        None
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn exceeded(&self, _exit: Option<ProcessExit>, _stderr: &str) -> Option<ResourceLimit> {
+        None
+    }
+}
+
+/// A boxed future, as returned by the client handler traits.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Produces completions for `sampling/createMessage` requests.
+pub trait SamplingBackend: Send + Sync {
+    fn create_message(&self, request: CreateMessageRequest) -> BoxFuture<Result<CreateMessageResult, String>>;
+}
+
+/// Decides whether a sampling request may be passed on to the model.
+pub trait SamplingApproval: Send + Sync {
+    fn approve(&self, request: CreateMessageRequest) -> BoxFuture<bool>;
+}
+
+/// Asks the user for the input described by an `elicitation/create` request.
+pub trait ElicitationHandler: Send + Sync {
+    fn elicit(&self, request: ElicitRequest) -> BoxFuture<Result<ElicitResult, String>>;
+}
+
+/// Handlers for requests the server sends to the client. Capabilities are
+/// only advertised for the handlers that are set.
+#[derive(Clone, Default)]
+pub struct ClientHandlers {
+    pub sampling: Option<Arc<dyn SamplingBackend>>,
+    /// Consulted before every sampling request; when unset requests go
+    /// straight to the backend.
+    pub sampling_approval: Option<Arc<dyn SamplingApproval>>,
+    /// Workspace folders reported by `roots/list`, shared with the host.
+    pub roots: Option<SharedRoots>,
+    pub elicitation: Option<Arc<dyn ElicitationHandler>>,
+}
+
+impl std::fmt::Debug for ClientHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientHandlers")
+            .field("sampling", &self.sampling.is_some())
+            .field("sampling_approval", &self.sampling_approval.is_some())
+            .field("roots", &self.roots)
+            .field("elicitation", &self.elicitation.is_some())
+            .finish()
+    }
+}
+
+impl ClientHandlers {
+    fn capabilities(&self) -> Value {
+        let mut capabilities = json!({});
+        if self.sampling.is_some() {
+            capabilities["sampling"] = json!({});
+        }
+        if self.roots.is_some() {
+            capabilities["roots"] = json!({ "listChanged": true });
+        }
+        if self.elicitation.is_some() {
+            capabilities["elicitation"] = json!({});
+        }
+        capabilities
+    }
+
+    // Prompts shown to the user are counted on `router` so tool call deadlines pause
+    async fn handle(&self, method: &str, params: Value, router: &Arc<MessageRouter>) -> Result<Value, RpcError> {
+        match method {
+            "ping" => Ok(json!({})),
+            "sampling/createMessage" if self.sampling.is_some() => self.create_message(params, router).await,
+            "roots/list" => match self.roots {
+                Some(ref roots) => Ok(json!({ "roots": *roots.read().unwrap() })),
+                None => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+            },
+            "elicitation/create" if self.elicitation.is_some() => self.elicit(params, router).await,
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        }
+    }
+
+    async fn create_message(&self, params: Value, router: &Arc<MessageRouter>) -> Result<Value, RpcError> {
+        let request: CreateMessageRequest = serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid sampling request: {}", e)))?;
+
+        if let Some(ref approval) = self.sampling_approval {
+            let waiting = WaitingOnUser::new(router);
+            let approved = approval.approve(request.clone()).await;
+            drop(waiting);
+            if !approved {
+                return Err(RpcError::new(USER_REJECTED, "User rejected sampling request".to_string()));
+            }
+        }
+
+        let backend = self.sampling.as_ref()
+            .ok_or_else(|| RpcError::new(METHOD_NOT_FOUND, "Sampling is not supported".to_string()))?;
+        let result = backend.create_message(request).await
+            .map_err(|e| RpcError::new(INTERNAL_ERROR, e))?;
+
+        serde_json::to_value(result).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+    }
+
+    async fn elicit(&self, params: Value, router: &Arc<MessageRouter>) -> Result<Value, RpcError> {
+        let request: ElicitRequest = serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid elicitation request: {}", e)))?;
+
+        let handler = self.elicitation.as_ref()
+            .ok_or_else(|| RpcError::new(METHOD_NOT_FOUND, "Elicitation is not supported".to_string()))?;
+        let _waiting = WaitingOnUser::new(router);
+        let result = handler.elicit(request).await
+            .map_err(|e| RpcError::new(INTERNAL_ERROR, e))?;
+
+        serde_json::to_value(result).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+    }
+}
+
+// The error member of a response to a server request
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: String) -> Self {
+        RpcError { code, message }
+    }
+}
+
+/// Why an operation on an MCP server failed.
+#[derive(Debug, Clone)]
+pub enum MCPError {
+    /// The server answered with a JSON-RPC error.
+    Rpc { code: i64, message: String, data: Option<Value> },
+    Timeout { method: String, timeout: Duration },
+    Cancelled { reason: Option<String> },
+    ConnectionClosed,
+    /// A local server process ended; `stderr` holds its last output and
+    /// `limit` the resource limit it ran into, when that is known.
+    ServerExited { code: Option<i32>, stderr: String, limit: Option<ResourceLimit> },
+    /// Connecting to a local server failed; `stderr` holds its last output.
+    Launch { reason: Box<MCPError>, stderr: String },
+    NotInitialized,
+    /// The server did not declare the capability the operation needs.
+    Unsupported { server: String, capability: String },
+    /// Tool arguments do not match the tool's `inputSchema`; nothing was sent.
+    InvalidArguments { tool: String, errors: Vec<ValidationError> },
+    /// A tool's `structuredContent` does not match its `outputSchema`.
+    InvalidOutput { tool: String, errors: Vec<ValidationError> },
+    /// The caller asked for something unusable, such as an unknown call id.
+    InvalidRequest(String),
+    /// The server sent something that does not follow the protocol.
+    Protocol(String),
+    /// An HTTP request to the server was answered with an error status.
+    Http { status: StatusCode },
+    Transport(String),
+}
+
+impl MCPError {
+    /// A stable identifier for the kind of failure.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MCPError::Rpc { .. } => "rpc_error",
+            MCPError::Timeout { .. } => "timeout",
+            MCPError::Cancelled { .. } => "cancelled",
+            MCPError::ConnectionClosed => "connection_closed",
+            MCPError::ServerExited { .. } => "server_exited",
+            MCPError::Launch { reason, .. } => reason.code(),
+            MCPError::NotInitialized => "not_initialized",
+            MCPError::Unsupported { .. } => "unsupported",
+            MCPError::InvalidArguments { .. } => "invalid_arguments",
+            MCPError::InvalidOutput { .. } => "invalid_output",
+            MCPError::InvalidRequest(_) => "invalid_request",
+            MCPError::Protocol(_) => "protocol_error",
+            MCPError::Http { .. } | MCPError::Transport(_) => "transport_error",
+        }
+    }
+}
+
+impl std::fmt::Display for MCPError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MCPError::Rpc { code, message, .. } => write!(f, "MCP error {}: {}", code, message),
+            MCPError::Timeout { method, timeout } => {
+                write!(f, "{} timed out after {}ms", method, timeout.as_millis())
+            }
+            MCPError::Cancelled { reason: Some(reason) } => write!(f, "Request cancelled: {}", reason),
+            MCPError::Cancelled { reason: None } => write!(f, "Request cancelled"),
+            MCPError::ConnectionClosed => write!(f, "MCP server closed connection"),
+            MCPError::ServerExited { code, stderr, limit } => {
+                match code {
+                    Some(code) => write!(f, "server exited with code {}", code)?,
+                    None => write!(f, "server exited")?,
+                }
+                if let Some(limit) = limit {
+                    write!(f, " after exceeding its {} limit", limit)?;
+                }
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+            MCPError::Launch { reason, stderr } => write!(f, "{} (stderr: {})", reason, stderr),
+            MCPError::NotInitialized => write!(f, "Client not initialized"),
+            MCPError::Unsupported { server, capability } => write!(f, "Server {} does not support {}", server, capability),
+            MCPError::InvalidArguments { tool, errors } => {
+                write!(f, "Invalid arguments for tool {}: {}", tool, join_errors(errors))
+            }
+            MCPError::InvalidOutput { tool, errors } => {
+                write!(f, "Invalid structured output from tool {}: {}", tool, join_errors(errors))
+            }
+            MCPError::InvalidRequest(message) => write!(f, "{}", message),
+            MCPError::Protocol(message) => write!(f, "Protocol error: {}", message),
+            MCPError::Http { status } => write!(f, "Transport error: HTTP {} from MCP server", status),
+            MCPError::Transport(message) => write!(f, "Transport error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MCPError {}
+
+fn join_errors(errors: &[ValidationError]) -> String {
+    errors.iter().map(ValidationError::to_string).collect::<Vec<_>>().join("; ")
+}
+
+impl From<std::io::Error> for MCPError {
+    fn from(error: std::io::Error) -> Self {
+        MCPError::Transport(error.to_string())
+    }
+}
+
+impl From<reqwest::Error> for MCPError {
+    fn from(error: reqwest::Error) -> Self {
+        MCPError::Transport(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for MCPError {
+    fn from(error: serde_json::Error) -> Self {
+        MCPError::Protocol(error.to_string())
+    }
+}
+
+/// Name and version of an MCP implementation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Implementation {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Features a server declared during initialization.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completions: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<Value>,
+}
+
+impl ServerCapabilities {
+    fn supports(&self, capability: &str) -> bool {
+        match capability {
+            "tools" => self.tools.is_some(),
+            "resources" => self.resources.is_some(),
+            "resources.subscribe" => self.resources.as_ref()
+                .and_then(|resources| resources.get("subscribe"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            "prompts" => self.prompts.is_some(),
+            "logging" => self.logging.is_some(),
+            "completions" => self.completions.is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// The server's answer to `initialize`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    pub protocol_version: String,
+    #[serde(default)]
+    pub capabilities: ServerCapabilities,
+    pub server_info: Implementation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+/// A tool as advertised by `tools/list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub input_schema: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Value>,
+}
+
+/// A resource as advertised by `resources/list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    pub uri: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Value>,
+}
+
+/// A directory or file the server may operate on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Root {
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// The current set of roots, updated by the host as the workspace changes.
+pub type SharedRoots = Arc<RwLock<Vec<Root>>>;
+
+/// A parameterized resource as advertised by `resources/templates/list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTemplate {
+    pub uri_template: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Value>,
+}
+
+/// The contents of a resource, either text or base64-encoded binary data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResourceContents {
+    Text {
+        uri: String,
+        #[serde(default, rename = "mimeType", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        text: String,
+    },
+    Blob {
+        uri: String,
+        #[serde(default, rename = "mimeType", skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        blob: String,
+    },
+}
+
+/// A piece of content in a tool result or prompt message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        annotations: Option<Value>,
+    },
+    /// Base64-encoded image data.
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        annotations: Option<Value>,
+    },
+    /// Base64-encoded audio data.
+    Audio {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        annotations: Option<Value>,
+    },
+    /// A link to a resource the client can read separately.
+    ResourceLink(Resource),
+    /// A resource embedded in full.
+    Resource {
+        resource: ResourceContents,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        annotations: Option<Value>,
+    },
+}
+
+/// The result of `tools/call`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    #[serde(default)]
+    pub content: Vec<ContentBlock>,
+    /// Output matching the tool's `outputSchema`, when it declares one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+    /// Whether the tool itself failed; such errors are meant for the model to see.
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+/// The result of `resources/read`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadResourceResult {
+    pub contents: Vec<ResourceContents>,
+}
+
+/// An argument a prompt accepts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A prompt as advertised by `prompts/list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prompt {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+}
+
+/// A message in a prompt returned by `prompts/get`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptMessage {
+    pub role: String,
+    pub content: ContentBlock,
+}
+
+/// The result of `prompts/get`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetPromptResult {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+/// What a `completion/complete` request completes an argument of.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CompletionReference {
+    #[serde(rename = "ref/prompt")]
+    Prompt { name: String },
+    /// A resource template, identified by its URI template.
+    #[serde(rename = "ref/resource")]
+    Resource { uri: String },
+}
+
+/// Suggestions returned by `completion/complete`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    pub values: Vec<String>,
+    /// How many suggestions exist in all, when more than `values` holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompleteResult {
+    completion: Completion,
+}
+
+/// A message in a sampling conversation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamplingMessage {
+    pub role: String,
+    pub content: Value,
+}
+
+/// The parameters of a `sampling/createMessage` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageRequest {
+    pub messages: Vec<SamplingMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_preferences: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_context: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    pub max_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+}
+
+/// The completion returned for a `sampling/createMessage` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageResult {
+    pub role: String,
+    pub content: Value,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
+
+/// The parameters of an `elicitation/create` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ElicitRequest {
+    pub message: String,
+    /// A flat JSON Schema object describing the fields the server needs.
+    pub requested_schema: Value,
+}
+
+/// How the user answered an elicitation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElicitAction {
+    Accept,
+    Decline,
+    Cancel,
+}
+
+/// The user's answer to an `elicitation/create` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElicitResult {
+    pub action: ElicitAction,
+    /// The submitted values, present only when the user accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Value>,
+}
+
+// Full tool and resource lists, kept until the server reports a change
+#[derive(Debug, Default)]
+struct Catalog {
+    tools: Mutex<Option<Vec<Tool>>>,
+    resources: Mutex<Option<Vec<Resource>>>,
+}
+
+impl Catalog {
+    async fn invalidate(&self) {
+        *self.tools.lock().await = None;
+        *self.resources.lock().await = None;
+    }
+
+    // Drops cached lists whenever the server announces they changed
+    async fn watch(self: Arc<Self>, mut notifications: broadcast::Receiver<MCPNotification>) {
+        loop {
+            match notifications.recv().await {
+                Ok(MCPNotification::ToolsListChanged) => *self.tools.lock().await = None,
+                Ok(MCPNotification::ResourcesListChanged) => *self.resources.lock().await = None,
+                Ok(_) => {}
+                // Missed notifications may have included a change
+                Err(broadcast::error::RecvError::Lagged(_)) => self.invalidate().await,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+/// Where a log entry came from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSource {
+    /// A `notifications/message` sent by the server.
+    Server,
+    /// A line the server process wrote to stderr.
+    Stderr,
+}
+
+/// One entry kept in a server's log buffer.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    /// Milliseconds since the Unix epoch when the entry was received.
+    pub timestamp: u64,
+    pub source: LogSource,
+    pub level: String,
+    pub logger: Option<String>,
+    pub data: Value,
+}
+
+// The most recent log messages from a server, oldest dropped first
+#[derive(Debug, Default)]
+struct LogBuffer {
+    entries: std::sync::Mutex<VecDeque<LogEntry>>,
+}
+
+impl LogBuffer {
+    fn push(&self, entry: LogEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == LOG_BUFFER_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    // The newest `limit` entries, oldest first
+    fn tail(&self, limit: usize) -> Vec<LogEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().skip(entries.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    async fn watch(self: Arc<Self>, mut notifications: broadcast::Receiver<MCPNotification>) {
+        loop {
+            match notifications.recv().await {
+                Ok(MCPNotification::Message { level, logger, data }) => self.push(LogEntry {
+                    timestamp: now_millis(),
+                    source: LogSource::Server,
+                    level,
+                    logger,
+                    data,
+                }),
+                Ok(MCPNotification::Stderr { line }) => self.push(LogEntry {
+                    timestamp: now_millis(),
+                    source: LogSource::Stderr,
+                    level: "info".to_string(),
+                    logger: None,
+                    data: Value::String(line),
+                }),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("[MCP] Log buffer missed {} notifications", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// How a server is doing according to recent health-check pings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    Healthy,
+    /// The last ping failed or was slow to answer.
+    Degraded,
+    /// Several pings in a row went unanswered.
+    Unresponsive,
+}
+
+/// The outcome of recent health-check pings.
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    pub state: HealthState,
+    /// How often pings are sent, in milliseconds; absent when checks are off.
+    pub interval_ms: Option<u64>,
+    /// Round-trip time of the last answered ping, in milliseconds.
+    pub latency_ms: Option<u64>,
+    /// Milliseconds since the Unix epoch when the last ping finished.
+    pub last_checked: Option<u64>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+impl Health {
+    fn new(interval: Option<Duration>) -> Self {
+        Health {
+            state: HealthState::Healthy,
+            interval_ms: interval.map(|interval| interval.as_millis() as u64),
+            latency_ms: None,
+            last_checked: None,
+            consecutive_failures: 0,
+            last_error: None,
+        }
+    }
+
+    fn record(&mut self, result: Result<Duration, MCPError>) {
+        self.last_checked = Some(now_millis());
+        match result {
+            Ok(latency) => {
+                self.latency_ms = Some(latency.as_millis() as u64);
+                self.consecutive_failures = 0;
+                self.last_error = None;
+                self.state = if latency >= SLOW_PING_LATENCY {
+                    HealthState::Degraded
+                } else {
+                    HealthState::Healthy
+                };
+            }
+            Err(e) => {
+                self.consecutive_failures += 1;
+                self.last_error = Some(e.to_string());
+                self.state = if self.consecutive_failures >= UNRESPONSIVE_AFTER_FAILURES {
+                    HealthState::Unresponsive
+                } else {
+                    HealthState::Degraded
+                };
+            }
+        }
+    }
+}
+
+/// A server-initiated JSON-RPC notification, a line of stderr output from a
+/// local server process, or a change in the server's health.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MCPNotification {
+    ToolsListChanged,
+    ResourcesListChanged,
+    PromptsListChanged,
+    ResourceUpdated {
+        uri: String,
+    },
+    Message {
+        level: String,
+        logger: Option<String>,
+        data: Value,
+    },
+    Progress {
+        #[serde(rename = "progressToken")]
+        progress_token: Value,
+        progress: f64,
+        total: Option<f64>,
+        message: Option<String>,
+    },
+    Stderr {
+        line: String,
+    },
+    Health(Health),
+    Other {
+        method: String,
+        params: Value,
+    },
+}
+
+impl MCPNotification {
+    fn from_message(method: &str, params: Value) -> Self {
+        match method {
+            "notifications/tools/list_changed" => MCPNotification::ToolsListChanged,
+            "notifications/resources/list_changed" => MCPNotification::ResourcesListChanged,
+            "notifications/prompts/list_changed" => MCPNotification::PromptsListChanged,
+            "notifications/resources/updated" => MCPNotification::ResourceUpdated {
+                uri: params["uri"].as_str().unwrap_or_default().to_string(),
+            },
+            "notifications/message" => MCPNotification::Message {
+                level: params["level"].as_str().unwrap_or("info").to_string(),
+                logger: params["logger"].as_str().map(String::from),
+                data: params["data"].clone(),
+            },
+            "notifications/progress" => MCPNotification::Progress {
+                progress_token: params["progressToken"].clone(),
+                progress: params["progress"].as_f64().unwrap_or_default(),
+                total: params["total"].as_f64(),
+                message: params["message"].as_str().map(String::from),
+            },
+            _ => MCPNotification::Other {
+                method: method.to_string(),
+                params,
+            },
+        }
+    }
+
+    /// Name of the Tauri event this notification is re-emitted as.
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            MCPNotification::ToolsListChanged => "mcp:tools-list-changed",
+            MCPNotification::ResourcesListChanged => "mcp:resources-list-changed",
+            MCPNotification::PromptsListChanged => "mcp:prompts-list-changed",
+            MCPNotification::ResourceUpdated { .. } => "mcp:resource-updated",
+            MCPNotification::Message { .. } => "mcp:message",
+            MCPNotification::Progress { .. } => "mcp:progress",
+            MCPNotification::Stderr { .. } => "mcp:stderr",
+            MCPNotification::Health(_) => "mcp:health",
+            MCPNotification::Other { .. } => "mcp:notification",
+        }
+    }
+}
+
+// Routes messages arriving from the server to waiting requests and notification subscribers
+#[derive(Debug)]
+struct MessageRouter {
+    // Replies waiting to be delivered, keyed by JSON-RPC request id
+    pending: Mutex<HashMap<u64, oneshot::Sender<Result<Value, MCPError>>>>,
+    notifications: broadcast::Sender<MCPNotification>,
+    // Requests from the server, answered by the client; dropped on close
+    requests: std::sync::Mutex<Option<mpsc::UnboundedSender<Value>>>,
+    incoming_requests: std::sync::Mutex<Option<mpsc::UnboundedReceiver<Value>>>,
+    // Server requests still being answered, keyed by their JSON-RPC id as JSON text
+    answering: std::sync::Mutex<HashMap<String, ServerRequest>>,
+    // Elicitations and sampling approvals waiting on the user; tool call
+    // deadlines are paused meanwhile
+    user_prompts: watch::Sender<usize>,
+    // Caller-chosen call ids mapped to the request they started, including
+    // calls still waiting for a slot
+    in_flight_calls: Mutex<HashMap<String, InFlightCall>>,
+    closed: watch::Sender<bool>,
+}
+
+// How waiting on a request ended: with a reply, a dropped sender, or the
+// timeout that passed first
+type ReplyOutcome = Result<Result<Result<Value, MCPError>, oneshot::error::RecvError>, Duration>;
+
+// A cancellable tool call
+#[derive(Debug, Clone, Copy)]
+struct InFlightCall {
+    // JSON-RPC id of its request
+    id: u64,
+    // False while it waits for a call slot, so the server has not seen it
+    sent: bool,
+}
+
+// A server request being answered on its own task
+#[derive(Debug)]
+struct ServerRequest {
+    // The tool call it arrived during, when that was the only one in flight
+    call: Option<u64>,
+    task: tokio::task::AbortHandle,
+}
+
+// Counts a prompt as waiting on the user until it is answered or dropped
+struct WaitingOnUser(Arc<MessageRouter>);
+
+impl WaitingOnUser {
+    fn new(router: &Arc<MessageRouter>) -> Self {
+        router.user_prompts.send_modify(|pending| *pending += 1);
+        WaitingOnUser(router.clone())
+    }
+}
+
+impl Drop for WaitingOnUser {
+    fn drop(&mut self) {
+        self.0.user_prompts.send_modify(|pending| *pending -= 1);
+    }
+}
+
+impl MessageRouter {
+    fn new() -> Self {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CHANNEL_CAPACITY);
+        let (requests, incoming_requests) = mpsc::unbounded_channel();
+        MessageRouter {
+            pending: Mutex::new(HashMap::new()),
+            notifications,
+            requests: std::sync::Mutex::new(Some(requests)),
+            incoming_requests: std::sync::Mutex::new(Some(incoming_requests)),
+            answering: std::sync::Mutex::new(HashMap::new()),
+            user_prompts: watch::channel(0).0,
+            in_flight_calls: Mutex::new(HashMap::new()),
+            closed: watch::channel(false).0,
+        }
+    }
+
+    // Parses one stdout line or event payload, which may hold a single message or a batch
+    async fn route_text(&self, text: &str) {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            return;
+        }
+
+        match serde_json::from_str::<Value>(trimmed) {
+            Ok(Value::Array(batch)) => {
+                for message in batch {
+                    self.route(message).await;
+                }
+            }
+            Ok(message) => self.route(message).await,
+            // Servers sometimes print banners or logs to stdout; skip them
+            Err(e) => println!("[MCP] Ignoring non-JSON message ({}): {:?}", e, trimmed),
+        }
+    }
+
+    async fn route(&self, message: Value) {
+        if let Some(method) = message.get("method").and_then(Value::as_str) {
+            if message.get("id").is_some() {
+                let requests = self.requests.lock().unwrap();
+                if let Some(requests) = requests.as_ref() {
+                    let _ = requests.send(message);
+                }
+            } else {
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                // No subscribers is fine, the notification is simply dropped
+                let _ = self.notifications.send(MCPNotification::from_message(method, params));
+            }
+            return;
+        }
+
+        if message.get("result").is_none() && message.get("error").is_none() {
+            println!("[MCP] Ignoring malformed message: {}", message);
+            return;
+        }
+
+        match message.get("id").and_then(Value::as_u64) {
+            Some(id) => {
+                if let Some(sender) = self.pending.lock().await.remove(&id) {
+                    let _ = sender.send(Ok(message));
+                } else {
+                    println!("[MCP] Received response for unknown request id {}", id);
+                }
+            }
+            None => println!("[MCP] Received response without a usable id: {}", message),
+        }
+    }
+
+    async fn fail(&self, id: u64, error: MCPError) {
+        if let Some(sender) = self.pending.lock().await.remove(&id) {
+            let _ = sender.send(Err(error));
+        }
+    }
+
+    // Wakes every waiting request; their replies can no longer arrive
+    async fn fail_all(&self) {
+        for (_, sender) in self.pending.lock().await.drain() {
+            let _ = sender.send(Err(MCPError::ConnectionClosed));
+        }
+    }
+
+    // Stops answering the server requests that arrived during tool call `call`,
+    // which nobody is waiting for any more
+    fn withdraw_requests(&self, call: u64) {
+        self.answering.lock().unwrap().retain(|_, request| {
+            if request.call == Some(call) {
+                request.task.abort();
+            }
+            request.call != Some(call)
+        });
+    }
+
+    // Marks the connection as gone for good
+    async fn close(&self) {
+        self.closed.send_replace(true);
+        self.requests.lock().unwrap().take();
+        for (_, request) in self.answering.lock().unwrap().drain() {
+            request.task.abort();
+        }
+        self.fail_all().await;
+    }
+}
+
+// Answers requests the server sends, each on its own task so a slow
+// sampling round-trip does not hold up pings
+async fn serve_requests(
+    mut requests: mpsc::UnboundedReceiver<Value>,
+    transport: Arc<Transport>,
+    handlers: ClientHandlers,
+    router: Arc<MessageRouter>,
+) {
+    while let Some(request) = requests.recv().await {
+        // Requests carry no link to the call that caused them, so one is
+        // only assumed when a single tool call is in flight
+        let call = {
+            let in_flight = router.in_flight_calls.lock().await;
+            let mut sent = in_flight.values().filter(|call| call.sent);
+            match (sent.next(), sent.next()) {
+                (Some(call), None) => Some(call.id),
+                _ => None,
+            }
+        };
+        let key = request["id"].to_string();
+
+        // Held until the request is recorded, so its task cannot finish first
+        let mut answering = router.answering.lock().unwrap();
+        let transport = transport.clone();
+        let handlers = handlers.clone();
+        let (task_router, task_key) = (router.clone(), key.clone());
+        let task = tokio::spawn(async move {
+            let method = request["method"].as_str().unwrap_or_default();
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            println!("[MCP] Handling server request: {}", method);
+
+            let response = match handlers.handle(method, params, &task_router).await {
+                Ok(result) => json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": result
+                }),
+                Err(error) => json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": error
+                }),
+            };
+
+            if let Err(e) = transport.send(&response).await {
+                println!("[MCP] Failed to answer server request {}: {}", method, e);
+            }
+            task_router.answering.lock().unwrap().remove(&task_key);
+        });
+        answering.insert(key, ServerRequest { call, task: task.abort_handle() });
+    }
+}
+
+// Sleeps until `deadline`, or forever without one
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Extracts the typed result from a response envelope; a malformed result is
+// reported here rather than handed on to the caller
+fn parse_result<T: DeserializeOwned>(method: &str, mut response: Value) -> Result<T, MCPError> {
+    serde_json::from_value(response["result"].take())
+        .map_err(|e| MCPError::Protocol(format!("Invalid {} result: {}", method, e)))
+}
+
+// Routes newline-delimited JSON-RPC until the stream ends
+async fn route_lines<R: AsyncBufRead + Unpin>(mut reader: R, router: &MessageRouter) {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line).await {
+            Ok(0) => {
+                println!("[MCP] Server closed the connection");
+                break;
+            }
+            Ok(_) => router.route_text(&line).await,
+            Err(e) => {
+                println!("[MCP] Failed to read from server: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+// How JSON-RPC messages travel between the client and a server
+#[derive(Debug)]
+enum Transport {
+    Stdio(Box<StdioTransport>),
+    StreamableHttp(HttpTransport),
+    LegacySse(SseTransport),
+    Socket(SocketTransport),
+}
+
+impl Transport {
+    async fn send(&self, message: &Value) -> Result<(), MCPError> {
+        match self {
+            Transport::Stdio(transport) => transport.send(message).await,
+            Transport::StreamableHttp(transport) => transport.send(message).await,
+            Transport::LegacySse(transport) => transport.send(message).await,
+            Transport::Socket(transport) => transport.send(message).await,
+        }
+    }
+
+    async fn close(&self) {
+        match self {
+            Transport::Stdio(transport) => transport.close().await,
+            Transport::StreamableHttp(transport) => transport.close().await,
+            Transport::LegacySse(transport) => transport.close().await,
+            Transport::Socket(transport) => transport.close().await,
+        }
+    }
+}
+
+// The last lines a child process wrote to stderr, kept for error reports
+#[derive(Debug)]
+struct StderrTail {
+    lines: std::sync::Mutex<VecDeque<String>>,
+    drained: watch::Sender<bool>,
+}
+
+impl StderrTail {
+    fn new() -> Self {
+        StderrTail {
+            lines: std::sync::Mutex::new(VecDeque::new()),
+            drained: watch::channel(false).0,
+        }
+    }
+
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == STDERR_TAIL_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn text(&self) -> String {
+        self.lines.lock().unwrap().iter().cloned().collect::<Vec<_>>().join("\n")
+    }
+
+    // A crashing server's last words may still be in the pipe when stdout closes
+    async fn wait_drained(&self) {
+        let mut drained = self.drained.subscribe();
+        let _ = tokio::time::timeout(STDERR_DRAIN_TIMEOUT, drained.wait_for(|drained| *drained)).await;
+    }
+}
+
+// Reads stderr until the process closes it, so a chatty server never blocks on a full pipe
+async fn drain_stderr<R: AsyncRead + Unpin>(stderr: R, tail: Arc<StderrTail>, router: Arc<MessageRouter>) {
+    let mut lines = BufReader::new(stderr).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                println!("[MCP] Server stderr: {}", line);
+                tail.push(line.clone());
+                let _ = router.notifications.send(MCPNotification::Stderr { line });
+            }
+            Ok(None) => break,
+            Err(e) => {
+                println!("[MCP] Failed to read server stderr: {}", e);
+                break;
+            }
+        }
+    }
+    tail.drained.send_replace(true);
+}
+
+// A local child process speaking newline-delimited JSON-RPC over stdin/stdout
+#[derive(Debug)]
+struct StdioTransport {
+    // Also the id of the process group the server runs in
+    pid: Option<u32>,
+    // Set once the process has ended
+    exited: watch::Receiver<Option<ProcessExit>>,
+    limits: ResourceLimits,
+    // Asks the task that owns the process to kill it
+    kill: Arc<Notify>,
+    stdin: Mutex<Option<ChildStdin>>,
+    stderr: Arc<StderrTail>,
+    reader_task: JoinHandle<()>,
+    stderr_task: JoinHandle<()>,
+}
+
+impl StdioTransport {
+    fn spawn(
+        command: String,
+        args: Vec<String>,
+        env: HashMap<String, String>,
+        limits: ResourceLimits,
+        router: Arc<MessageRouter>,
+    ) -> Result<Self, MCPError> {
+        println!("[MCP] Starting server: {} {:?}", command, args);
+        if !env.is_empty() {
+            println!("[MCP] Environment variables: {:?}", env.keys().collect::<Vec<_>>());
+        }
+
+        let mut cmd = Command::new(&command);
+        cmd.args(&args);
+        cmd.stdin(std::process::Stdio::piped());
+        cmd.stdout(std::process::Stdio::piped());
+        cmd.stderr(std::process::Stdio::piped());
+
+        // Add environment variables
+        for (key, value) in env {
+            cmd.env(key, value);
+        }
+
+        // Launchers like npx and uvx run the real server as a grandchild; a
+        // group of its own lets shutdown reach every process it started
+        #[cfg(unix)]
+        cmd.process_group(0);
+        limits.apply(&mut cmd);
+
+        let mut process = cmd.spawn()?;
+        let pid = process.id();
+
+        let stdin = process.stdin.take();
+        let stdout = process.stdout.take().ok_or_else(|| MCPError::Transport("No stdout available".to_string()))?;
+        let stderr = process.stderr.take().ok_or_else(|| MCPError::Transport("No stderr available".to_string()))?;
+
+        let tail = Arc::new(StderrTail::new());
+        let stderr_task = tokio::spawn(drain_stderr(stderr, tail.clone(), router.clone()));
+        let (exited_sender, exited) = watch::channel(None);
+        let kill = Arc::new(Notify::new());
+        tokio::spawn(watch_exit(process, kill.clone(), exited_sender, router.clone()));
+        let reader_task = tokio::spawn(async move {
+            route_lines(BufReader::new(stdout), &router).await;
+            router.close().await;
+        });
+
+        Ok(StdioTransport {
+            pid,
+            exited,
+            limits,
+            kill,
+            stdin: Mutex::new(stdin),
+            stderr: tail,
+            reader_task,
+            stderr_task,
+        })
+    }
+
+    // Describes why the connection ended, with the process's exit code and last stderr lines
+    async fn exit_error(&self) -> MCPError {
+        self.stderr.wait_drained().await;
+        // Closed pipes usually mean the process is gone, but it may not be reaped yet
+        let exit = self.wait_exit(STDERR_DRAIN_TIMEOUT).await;
+        let stderr = self.stderr.text();
+        let limit = self.limits.exceeded(exit, &stderr);
+        MCPError::ServerExited { code: exit.and_then(|exit| exit.code), stderr, limit }
+    }
+
+    // How the process ended, or None if it is still running after `timeout`
+    async fn wait_exit(&self, timeout: Duration) -> Option<ProcessExit> {
+        let mut exited = self.exited.clone();
+        let code = match tokio::time::timeout(timeout, exited.wait_for(Option::is_some)).await {
+            Ok(Ok(code)) => *code,
+            _ => None,
+        };
+        code
+    }
+
+    async fn send(&self, message: &Value) -> Result<(), MCPError> {
+        let mut stdin = self.stdin.lock().await;
+        if let Some(ref mut stdin) = *stdin {
+            let message_str = serde_json::to_string(message)?;
+            stdin.write_all(format!("{}\n", message_str).as_bytes()).await?;
+            stdin.flush().await?;
+            Ok(())
+        } else {
+            Err(MCPError::Transport("No stdin available".to_string()))
+        }
+    }
+
+    // Stops the server the way the MCP stdio transport prescribes: close its
+    // stdin, wait for it to exit, then SIGTERM, then SIGKILL
+    async fn close(&self) {
+        self.stdin.lock().await.take();
+
+        if self.wait_exit(SHUTDOWN_GRACE_PERIOD).await.is_none() {
+            println!("[MCP] Server did not exit after stdin closed, sending SIGTERM");
+            #[cfg(unix)]
+            self.signal_group(libc::SIGTERM);
+            if self.wait_exit(SHUTDOWN_GRACE_PERIOD).await.is_none() {
+                println!("[MCP] Server did not exit after SIGTERM, killing it");
+                self.kill.notify_one();
+                self.wait_exit(SHUTDOWN_GRACE_PERIOD).await;
+            }
+        }
+        // Helpers the server started can outlive it, still holding its group
+        #[cfg(unix)]
+        self.signal_group(libc::SIGKILL);
+
+        self.reader_task.abort();
+        self.stderr_task.abort();
+    }
+
+    // Sends `signal` to every process in the server's group
+    #[cfg(unix)]
+    fn signal_group(&self, signal: libc::c_int) {
+        if let Some(pid) = self.pid {
+            // SAFETY: killpg has no memory-safety preconditions; at worst the group is already gone
+            unsafe {
+                libc::killpg(pid as libc::pid_t, signal);
+            }
+        }
+    }
+}
+
+// How a server process ended
+#[derive(Debug, Clone, Copy)]
+struct ProcessExit {
+    code: Option<i32>,
+    // The signal that killed it, if any
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    signal: Option<i32>,
+}
+
+impl ProcessExit {
+    fn from_status(status: std::process::ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+        ProcessExit { code: status.code(), signal }
+    }
+}
+
+// Owns the server process until it ends, killing it when asked. The router is
+// closed on exit even if a helper the server started still holds stdout open.
+async fn watch_exit(
+    mut process: Child,
+    kill: Arc<Notify>,
+    exited: watch::Sender<Option<ProcessExit>>,
+    router: Arc<MessageRouter>,
+) {
+    let status = loop {
+        tokio::select! {
+            status = process.wait() => break status,
+            _ = kill.notified() => {}
+        }
+        let _ = process.start_kill();
+    };
+    let exit = match status {
+        Ok(status) => ProcessExit::from_status(status),
+        Err(_) => ProcessExit { code: None, signal: None },
+    };
+    exited.send_replace(Some(exit));
+
+    // Give the reader a moment to route what the server wrote before exiting
+    let mut closed = router.closed.subscribe();
+    let _ = tokio::time::timeout(STDERR_DRAIN_TIMEOUT, closed.wait_for(|closed| *closed)).await;
+    router.close().await;
+}
+
+/// Address of an already-running server that speaks newline-delimited JSON-RPC.
+#[derive(Debug, Clone)]
+pub enum SocketEndpoint {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl SocketEndpoint {
+    /// Parses `tcp://host:port` or `unix:///path`; anything else is not a socket endpoint.
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(address) = value.strip_prefix("tcp://") {
+            Some(SocketEndpoint::Tcp(address.to_string()))
+        } else {
+            value
+                .strip_prefix("unix://")
+                .map(|path| SocketEndpoint::Unix(PathBuf::from(path)))
+        }
+    }
+
+    async fn open(&self) -> Result<(SocketReader, SocketWriter), MCPError> {
+        match self {
+            SocketEndpoint::Tcp(address) => {
+                let (reader, writer) = TcpStream::connect(address).await?.into_split();
+                Ok((Box::new(reader), Box::new(writer)))
+            }
+            #[cfg(unix)]
+            SocketEndpoint::Unix(path) => {
+                let (reader, writer) = tokio::net::UnixStream::connect(path).await?.into_split();
+                Ok((Box::new(reader), Box::new(writer)))
+            }
+            #[cfg(not(unix))]
+            SocketEndpoint::Unix(_) => Err(MCPError::InvalidRequest("Unix sockets are not supported on this platform".to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for SocketEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SocketEndpoint::Tcp(address) => write!(f, "tcp://{}", address),
+            SocketEndpoint::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+type SocketReader = Box<dyn AsyncRead + Send + Unpin>;
+type SocketWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+// A long-lived server reached over TCP or a Unix socket. Unlike stdio the
+// connection can drop while the server keeps running, so it can be reopened
+struct SocketTransport {
+    endpoint: SocketEndpoint,
+    router: Arc<MessageRouter>,
+    writer: Mutex<Option<SocketWriter>>,
+    reader_task: Mutex<Option<JoinHandle<()>>>,
+    connected: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for SocketTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SocketTransport")
+            .field("endpoint", &self.endpoint)
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+impl SocketTransport {
+    async fn connect(
+        endpoint: SocketEndpoint,
+        router: Arc<MessageRouter>,
+    ) -> Result<Self, MCPError> {
+        let transport = SocketTransport {
+            endpoint,
+            router,
+            writer: Mutex::new(None),
+            reader_task: Mutex::new(None),
+            connected: Arc::new(AtomicBool::new(false)),
+        };
+        transport.open().await?;
+        Ok(transport)
+    }
+
+    async fn open(&self) -> Result<(), MCPError> {
+        println!("[MCP] Connecting to socket: {}", self.endpoint);
+        let (reader, writer) = self.endpoint.open().await?;
+        *self.writer.lock().await = Some(writer);
+        self.connected.store(true, Ordering::SeqCst);
+
+        let router = self.router.clone();
+        let connected = self.connected.clone();
+        let reader_task = tokio::spawn(async move {
+            route_lines(BufReader::new(reader), &router).await;
+            println!("[MCP] Socket disconnected");
+            connected.store(false, Ordering::SeqCst);
+            router.fail_all().await;
+        });
+
+        if let Some(previous) = self.reader_task.lock().await.replace(reader_task) {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    async fn send(&self, message: &Value) -> Result<(), MCPError> {
+        let mut writer = self.writer.lock().await;
+        let Some(stream) = writer.as_mut() else {
+            return Err(MCPError::ConnectionClosed);
+        };
+
+        let message_str = serde_json::to_string(message)?;
+        let written = async {
+            stream.write_all(format!("{}\n", message_str).as_bytes()).await?;
+            stream.flush().await
+        }.await;
+
+        if let Err(e) = written {
+            self.connected.store(false, Ordering::SeqCst);
+            *writer = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    async fn close(&self) {
+        if let Some(mut writer) = self.writer.lock().await.take() {
+            let _ = writer.shutdown().await;
+        }
+        if let Some(task) = self.reader_task.lock().await.take() {
+            task.abort();
+        }
+        self.connected.store(false, Ordering::SeqCst);
+    }
+}
+
+// A remote server reached through the MCP Streamable HTTP transport: every
+// message is POSTed to one endpoint and replies come back as JSON or SSE
+#[derive(Debug)]
+struct HttpTransport {
+    shared: Arc<HttpShared>,
+    listener_task: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Debug)]
+struct HttpShared {
+    client: reqwest::Client,
+    url: String,
+    headers: HeaderMap,
+    session_id: RwLock<Option<String>>,
+    protocol_version: RwLock<Option<String>>,
+    router: Arc<MessageRouter>,
+}
+
+fn build_header_map(headers: HashMap<String, String>) -> Result<HeaderMap, MCPError> {
+    let mut header_map = HeaderMap::new();
+    for (key, value) in headers {
+        let name = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|e| MCPError::InvalidRequest(format!("Invalid header name {}: {}", key, e)))?;
+        let value = HeaderValue::from_str(&value)
+            .map_err(|e| MCPError::InvalidRequest(format!("Invalid value for header {}: {}", key, e)))?;
+        header_map.insert(name, value);
+    }
+    Ok(header_map)
+}
+
+impl HttpTransport {
+    fn new(url: String, headers: HeaderMap, router: Arc<MessageRouter>) -> Self {
+        HttpTransport {
+            shared: Arc::new(HttpShared {
+                client: reqwest::Client::new(),
+                url,
+                headers,
+                session_id: RwLock::new(None),
+                protocol_version: RwLock::new(None),
+                router,
+            }),
+            listener_task: Mutex::new(None),
+        }
+    }
+
+    async fn send(&self, message: &Value) -> Result<(), MCPError> {
+        let request_id = message.get("method").and(message.get("id")).and_then(Value::as_u64);
+
+        match request_id {
+            // The reply may take as long as the request itself, so read it in
+            // the background and let the request's own timeout apply
+            Some(id) => {
+                let shared = self.shared.clone();
+                let message = message.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = shared.post(&message).await {
+                        shared.router.fail(id, e).await;
+                    }
+                });
+                Ok(())
+            }
+            None => self.shared.post(message).await,
+        }
+    }
+
+    // Opens the optional GET stream a server uses for messages outside of any request
+    async fn start_listening(&self) {
+        let shared = self.shared.clone();
+        let task = tokio::spawn(async move {
+            if let Err(e) = shared.listen().await {
+                println!("[MCP] Server event stream ended: {}", e);
+            }
+        });
+        *self.listener_task.lock().await = Some(task);
+    }
+
+    async fn close(&self) {
+        if let Some(task) = self.listener_task.lock().await.take() {
+            task.abort();
+        }
+
+        // Tell the server the session is over so it can free its state, but
+        // do not let an unreachable one hold up closing
+        let has_session = self.shared.session_id.read().unwrap().is_some();
+        if has_session {
+            let _ = self.shared.request(Method::DELETE).timeout(SHUTDOWN_GRACE_PERIOD).send().await;
+        }
+    }
+}
+
+impl HttpShared {
+    fn request(&self, method: Method) -> reqwest::RequestBuilder {
+        let mut builder = self.client.request(method, &self.url).headers(self.headers.clone());
+        let session_id = self.session_id.read().unwrap().clone();
+        if let Some(session_id) = session_id {
+            builder = builder.header(SESSION_ID_HEADER, session_id);
+        }
+        // Required on every request after the version has been negotiated
+        let protocol_version = self.protocol_version.read().unwrap().clone();
+        if let Some(protocol_version) = protocol_version {
+            builder = builder.header(PROTOCOL_VERSION_HEADER, protocol_version);
+        }
+        builder
+    }
+
+    async fn post(&self, message: &Value) -> Result<(), MCPError> {
+        let response = self.request(Method::POST)
+            .header(ACCEPT, "application/json, text/event-stream")
+            .json(message)
+            .send()
+            .await?;
+
+        // The server assigns a session when it answers initialize
+        if let Some(session_id) = response.headers().get(SESSION_ID_HEADER).and_then(|v| v.to_str().ok()) {
+            *self.session_id.write().unwrap() = Some(session_id.to_string());
+        }
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND && self.session_id.read().unwrap().is_some() {
+            return Err(MCPError::Transport("MCP session expired".to_string()));
+        }
+        if !status.is_success() {
+            return Err(MCPError::Http { status });
+        }
+        if status == StatusCode::ACCEPTED {
+            return Ok(());
+        }
+
+        let content_type = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        if content_type.starts_with("text/event-stream") {
+            self.read_event_stream(response).await
+        } else {
+            let body = response.text().await?;
+            self.router.route_text(&body).await;
+            Ok(())
+        }
+    }
+
+    async fn listen(&self) -> Result<(), MCPError> {
+        let response = self.request(Method::GET)
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::METHOD_NOT_ALLOWED {
+            println!("[MCP] Server does not offer a standalone event stream");
+            return Ok(());
+        }
+        if !status.is_success() {
+            return Err(MCPError::Http { status });
+        }
+
+        self.read_event_stream(response).await
+    }
+
+    async fn read_event_stream(&self, mut response: reqwest::Response) -> Result<(), MCPError> {
+        let mut parser = SseParser::default();
+        while let Some(chunk) = response.chunk().await? {
+            for event in parser.feed(&chunk) {
+                if event.event == "message" {
+                    self.router.route_text(&event.data).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// A remote server using the 2024-11-05 HTTP+SSE transport: the client keeps a
+// GET event stream open and POSTs messages to the endpoint it announces
+#[derive(Debug)]
+struct SseTransport {
+    client: reqwest::Client,
+    endpoint: String,
+    headers: HeaderMap,
+    listener_task: JoinHandle<()>,
+}
+
+impl SseTransport {
+    async fn connect(
+        url: String,
+        headers: HeaderMap,
+        router: Arc<MessageRouter>,
+        timeout: Duration,
+    ) -> Result<Self, MCPError> {
+        let client = reqwest::Client::new();
+        let response = client.get(&url)
+            .headers(headers.clone())
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(MCPError::Http { status: response.status() });
+        }
+
+        let base_url = response.url().clone();
+        let (endpoint_sender, endpoint_receiver) = oneshot::channel();
+        let listener_task = tokio::spawn(Self::read_loop(response, router, endpoint_sender));
+
+        // The server must announce where to POST before anything can be sent
+        let endpoint = match tokio::time::timeout(timeout, endpoint_receiver).await {
+            Ok(Ok(endpoint)) => match base_url.join(&endpoint) {
+                Ok(endpoint) => endpoint.to_string(),
+                Err(e) => {
+                    listener_task.abort();
+                    return Err(MCPError::Protocol(format!("Invalid SSE endpoint {}: {}", endpoint, e)));
+                }
+            },
+            Ok(Err(_)) => {
+                listener_task.abort();
+                return Err(MCPError::Protocol("SSE stream ended before the server announced its endpoint".to_string()));
+            }
+            Err(_) => {
+                listener_task.abort();
+                return Err(MCPError::Timeout { method: "SSE endpoint event".to_string(), timeout });
+            }
+        };
+        println!("[MCP] SSE endpoint: {}", endpoint);
+
+        Ok(SseTransport {
+            client,
+            endpoint,
+            headers,
+            listener_task,
+        })
+    }
+
+    async fn read_loop(
+        mut response: reqwest::Response,
+        router: Arc<MessageRouter>,
+        endpoint_sender: oneshot::Sender<String>,
+    ) {
+        let mut endpoint_sender = Some(endpoint_sender);
+        let mut parser = SseParser::default();
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    for event in parser.feed(&chunk) {
+                        match event.event.as_str() {
+                            "endpoint" => {
+                                if let Some(sender) = endpoint_sender.take() {
+                                    let _ = sender.send(event.data.trim().to_string());
+                                }
+                            }
+                            "message" => router.route_text(&event.data).await,
+                            other => println!("[MCP] Ignoring SSE event: {}", other),
+                        }
+                    }
+                }
+                Ok(None) => {
+                    println!("[MCP] Server closed SSE stream");
+                    break;
+                }
+                Err(e) => {
+                    println!("[MCP] Failed to read SSE stream: {}", e);
+                    break;
+                }
+            }
+        }
+
+        router.close().await;
+    }
+
+    async fn send(&self, message: &Value) -> Result<(), MCPError> {
+        // Replies arrive on the event stream, the POST itself is only acknowledged
+        let response = self.client.post(&self.endpoint)
+            .headers(self.headers.clone())
+            .json(message)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(MCPError::Http { status: response.status() });
+        }
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.listener_task.abort();
+    }
+}
+
+#[derive(Debug)]
+struct SseEvent {
+    event: String,
+    data: String,
+}
+
+// Incremental parser for `text/event-stream` bodies
+#[derive(Debug, Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            // A blank line dispatches the event collected so far
+            if line.is_empty() {
+                let event = self.event.take().unwrap_or_else(|| "message".to_string());
+                if !self.data.is_empty() {
+                    events.push(SseEvent { event, data: self.data.join("\n") });
+                    self.data.clear();
+                }
+                continue;
+            }
+
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        events
+    }
+}
 
 #[derive(Debug)]
 pub struct MCPClient {
-    process: Child,
-    stdin: Option<ChildStdin>,
-    stdout: Option<BufReader<ChildStdout>>,
-    request_id_counter: u64,
-    initialized: bool,
+    transport: Arc<Transport>,
+    router: Arc<MessageRouter>,
+    server_info: RwLock<Option<InitializeResult>>,
+    catalog: Arc<Catalog>,
+    logs: Arc<LogBuffer>,
+    log_level: RwLock<String>,
+    // Resource URIs with an active `resources/subscribe`
+    subscriptions: Mutex<HashSet<String>>,
+    reconnect_lock: Mutex<()>,
+    handlers: ClientHandlers,
+    request_timeout: Duration,
+    tool_timeout: Option<Duration>,
+    validate_output: bool,
+    health: std::sync::Mutex<Health>,
+    health_check_interval: Option<Duration>,
+    // Bounds tool calls in flight when the server has a concurrency limit
+    call_slots: Option<Semaphore>,
+    request_id_counter: AtomicU64,
+    initialized: AtomicBool,
 }
 
-impl MCPClient {
-    // pub async fn new(command: String, args: Vec<String>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-    //     Self::new_with_env(command, args, HashMap::new()).await
-    // }
+impl MCPClient {
+    // pub async fn new(command: String, args: Vec<String>) -> Result<Self, MCPError> {
+    //     Self::new_with_env(command, args, HashMap::new(), MCPClientOptions::default()).await
+    // }
+
+    pub async fn new_with_env(
+        command: String,
+        args: Vec<String>,
+        env: HashMap<String, String>,
+        options: MCPClientOptions,
+    ) -> Result<Self, MCPError> {
+        let router = Arc::new(MessageRouter::new());
+        let transport = StdioTransport::spawn(command, args, env, options.limits.clone(), router.clone())?;
+        Self::connect(Transport::Stdio(Box::new(transport)), router, options).await
+    }
+
+    /// Connects to a remote server over the Streamable HTTP transport, falling
+    /// back to the legacy HTTP+SSE transport if the server rejects the
+    /// initialize POST with 400, 404 or 405.
+    pub async fn new_http(
+        url: String,
+        headers: HashMap<String, String>,
+        options: MCPClientOptions,
+    ) -> Result<Self, MCPError> {
+        println!("[MCP] Connecting to HTTP server: {}", url);
+
+        let headers = build_header_map(headers)?;
+        let router = Arc::new(MessageRouter::new());
+        let transport = HttpTransport::new(url.clone(), headers.clone(), router.clone());
+
+        match Self::connect(Transport::StreamableHttp(transport), router, options.clone()).await {
+            Ok(client) => {
+                if let Transport::StreamableHttp(ref transport) = *client.transport {
+                    transport.start_listening().await;
+                }
+                Ok(client)
+            }
+            // Servers on the older HTTP+SSE transport reject the initialize
+            // POST outright; any other failure is passed through as is
+            Err(streamable_error @ MCPError::Http { status })
+                if [StatusCode::BAD_REQUEST, StatusCode::NOT_FOUND, StatusCode::METHOD_NOT_ALLOWED].contains(&status) =>
+            {
+                println!("[MCP] Streamable HTTP handshake failed ({}), trying HTTP+SSE", streamable_error);
+                Self::connect_sse(url, headers, options).await.map_err(|sse_error| {
+                    MCPError::Transport(format!(
+                        "Streamable HTTP failed: {}; HTTP+SSE failed: {}",
+                        streamable_error, sse_error
+                    ))
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Connects to an already-running server listening on a TCP or Unix socket.
+    pub async fn new_socket(
+        endpoint: SocketEndpoint,
+        options: MCPClientOptions,
+    ) -> Result<Self, MCPError> {
+        let router = Arc::new(MessageRouter::new());
+        let transport = SocketTransport::connect(endpoint, router.clone()).await?;
+        Self::connect(Transport::Socket(transport), router, options).await
+    }
+
+    async fn connect_sse(
+        url: String,
+        headers: HeaderMap,
+        options: MCPClientOptions,
+    ) -> Result<Self, MCPError> {
+        let router = Arc::new(MessageRouter::new());
+        let timeout = Self::request_timeout(&options);
+        let transport = SseTransport::connect(url, headers, router.clone(), timeout).await?;
+        Self::connect(Transport::LegacySse(transport), router, options).await
+    }
+
+    async fn connect(
+        transport: Transport,
+        router: Arc<MessageRouter>,
+        options: MCPClientOptions,
+    ) -> Result<Self, MCPError> {
+        let catalog = Arc::new(Catalog::default());
+        tokio::spawn(catalog.clone().watch(router.notifications.subscribe()));
+        let logs = Arc::new(LogBuffer::default());
+        tokio::spawn(logs.clone().watch(router.notifications.subscribe()));
+
+        let transport = Arc::new(transport);
+        if let Some(requests) = router.incoming_requests.lock().unwrap().take() {
+            tokio::spawn(serve_requests(requests, transport.clone(), options.handlers.clone(), router.clone()));
+        }
+
+        let client = MCPClient {
+            transport,
+            router,
+            server_info: RwLock::new(None),
+            catalog,
+            logs,
+            log_level: RwLock::new(options.log_level.clone().unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string())),
+            subscriptions: Mutex::new(HashSet::new()),
+            reconnect_lock: Mutex::new(()),
+            request_timeout: Self::request_timeout(&options),
+            tool_timeout: Self::tool_timeout(&options),
+            validate_output: options.validate_output,
+            health: std::sync::Mutex::new(Health::new(Self::health_check_interval(&options))),
+            health_check_interval: Self::health_check_interval(&options),
+            call_slots: options.limits.max_concurrent_calls.map(|slots| Semaphore::new(slots.get())),
+            handlers: options.handlers,
+            request_id_counter: AtomicU64::new(1),
+            initialized: AtomicBool::new(false),
+        };
+
+        if let Err(e) = client.initialize().await {
+            let e = client.with_stderr(e).await;
+            client.transport.close().await;
+            client.router.close().await;
+            return Err(e);
+        }
+        client.apply_log_level().await;
+
+        Ok(client)
+    }
+
+    // Adds a local server's recent stderr to a connection error, where the
+    // reason a launch failed usually is
+    async fn with_stderr(
+        &self,
+        error: MCPError,
+    ) -> MCPError {
+        let Transport::Stdio(ref transport) = *self.transport else {
+            return error;
+        };
+        if matches!(error, MCPError::ServerExited { .. }) {
+            return error;
+        }
+
+        transport.stderr.wait_drained().await;
+        let stderr = transport.stderr.text();
+        if stderr.is_empty() {
+            error
+        } else {
+            MCPError::Launch { reason: Box::new(error), stderr }
+        }
+    }
+
+    // The error for a request whose connection went away
+    async fn closed_error(&self) -> MCPError {
+        match *self.transport {
+            Transport::Stdio(ref transport) => transport.exit_error().await,
+            _ => MCPError::ConnectionClosed,
+        }
+    }
+
+    fn request_timeout(options: &MCPClientOptions) -> Duration {
+        options
+            .request_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    fn tool_timeout(options: &MCPClientOptions) -> Option<Duration> {
+        match options.tool_timeout_ms {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => Some(DEFAULT_TOOL_TIMEOUT),
+        }
+    }
+
+    fn health_check_interval(options: &MCPClientOptions) -> Option<Duration> {
+        match options.health_check_interval_ms {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => Some(DEFAULT_HEALTH_CHECK_INTERVAL),
+        }
+    }
+
+    async fn initialize(&self) -> Result<(), MCPError> {
+        let params = json!({
+            "protocolVersion": SUPPORTED_PROTOCOL_VERSIONS[0],
+            "capabilities": self.handlers.capabilities(),
+            "clientInfo": {
+                "name": "asetta",
+                "version": "0.1.0"
+            }
+        });
+
+        println!("[MCP] Initializing connection...");
+        let response = self.dispatch_request("initialize", Some(params), Some(self.request_timeout), None).await?;
+        let result: InitializeResult = serde_json::from_value(response["result"].clone())
+            .map_err(|e| MCPError::Protocol(format!("Invalid initialize result: {}", e)))?;
+
+        // The server answers with our version or the one it prefers; if we
+        // cannot speak that one the connection is unusable
+        if !SUPPORTED_PROTOCOL_VERSIONS.contains(&result.protocol_version.as_str()) {
+            return Err(MCPError::Protocol(format!(
+                "Server requires unsupported protocol version {} (supported: {})",
+                result.protocol_version,
+                SUPPORTED_PROTOCOL_VERSIONS.join(", ")
+            )));
+        }
+
+        println!(
+            "[MCP] Connected to {} {} using protocol {}",
+            result.server_info.name, result.server_info.version, result.protocol_version
+        );
+        if let Transport::StreamableHttp(ref transport) = *self.transport {
+            *transport.shared.protocol_version.write().unwrap() = Some(result.protocol_version.clone());
+        }
+        *self.server_info.write().unwrap() = Some(result);
+
+        // Send initialized notification
+        let initialized = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized"
+        });
+
+        self.send_notification(initialized).await?;
+        self.initialized.store(true, Ordering::SeqCst);
+        println!("[MCP] Connection initialized successfully");
+
+        Ok(())
+    }
+
+    /// Subscribe to notifications sent by the server from now on.
+    pub fn subscribe_notifications(&self) -> broadcast::Receiver<MCPNotification> {
+        self.router.notifications.subscribe()
+    }
+
+    fn next_request_id(&self) -> u64 {
+        self.request_id_counter.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn ensure_initialized(&self) -> Result<(), MCPError> {
+        if !self.initialized.load(Ordering::SeqCst) {
+            return Err(MCPError::NotInitialized);
+        }
+        Ok(())
+    }
+
+    /// The server's health according to the most recent pings.
+    pub fn health(&self) -> Health {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Sends a `ping` and returns how long the server took to answer. An
+    /// error reply counts as an answer, since servers that predate `ping`
+    /// reject it with method not found while working normally.
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration, MCPError> {
+        let started = Instant::now();
+        match self.send_request_with("ping", None, Some(timeout), None).await {
+            Ok(_) | Err(MCPError::Rpc { .. }) => Ok(started.elapsed()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pings the server every health check interval until the connection
+    /// closes, broadcasting [`MCPNotification::Health`] whenever its state changes.
+    pub fn start_health_checks(self: &Arc<Self>) {
+        let Some(interval) = self.health_check_interval else {
+            return;
+        };
+        // A weak reference, so the checks never keep a dropped client alive
+        let client = Arc::downgrade(self);
+        let mut closed = self.router.closed.subscribe();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = closed.wait_for(|closed| *closed) => break,
+                }
+                let Some(client) = client.upgrade() else {
+                    break;
+                };
+                // A ping still outstanding at the next check counts as failed
+                let result = client.ping(interval.min(client.request_timeout)).await;
+                client.record_ping(result);
+            }
+        });
+    }
+
+    fn record_ping(&self, result: Result<Duration, MCPError>) {
+        let changed = {
+            let mut health = self.health.lock().unwrap();
+            let before = health.state;
+            health.record(result);
+            (health.state != before).then(|| health.clone())
+        };
+        if let Some(health) = changed {
+            println!("[MCP] Server health is now {:?}", health.state);
+            let _ = self.router.notifications.send(MCPNotification::Health(health));
+        }
+    }
 
-    pub async fn new_with_env(
-        command: String, 
-        args: Vec<String>, 
-        env: HashMap<String, String>
-    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        println!("[MCP] Starting server: {} {:?}", command, args);
-        if !env.is_empty() {
-            println!("[MCP] Environment variables: {:?}", env.keys().collect::<Vec<_>>());
+    /// What the server reported about itself when the connection was initialized.
+    pub fn server_info(&self) -> Option<InitializeResult> {
+        self.server_info.read().unwrap().clone()
+    }
+
+    // Rejects requests for features the server never declared, instead of
+    // sending them and relying on the server's error
+    fn require_capability(&self, capability: &str) -> Result<(), MCPError> {
+        self.ensure_initialized()?;
+
+        let server_info = self.server_info.read().unwrap();
+        match server_info.as_ref() {
+            Some(info) if info.capabilities.supports(capability) => Ok(()),
+            Some(info) => Err(MCPError::Unsupported {
+                server: info.server_info.name.clone(),
+                capability: capability.to_string(),
+            }),
+            None => Err(MCPError::NotInitialized),
         }
-        
-        let mut cmd = Command::new(&command);
-        cmd.args(&args);
-        cmd.stdin(std::process::Stdio::piped());
-        cmd.stdout(std::process::Stdio::piped());
-        cmd.stderr(std::process::Stdio::piped());
+    }
 
-        // Add environment variables
-        for (key, value) in env {
-            cmd.env(key, value);
+    /// Every tool the server offers, served from cache until the list changes.
+    pub async fn list_tools(&self) -> Result<Vec<Tool>, MCPError> {
+        self.require_capability("tools")?;
+
+        // Holding the lock while fetching lets concurrent callers share one fetch
+        let mut tools = self.catalog.tools.lock().await;
+        if let Some(ref tools) = *tools {
+            return Ok(tools.clone());
         }
 
-        let mut process = cmd.spawn()?;
+        let fetched: Vec<Tool> = self.list_all("tools/list", "tools").await?;
+        println!("[MCP] Cached {} tools", fetched.len());
+        *tools = Some(fetched.clone());
+        Ok(fetched)
+    }
 
-        let stdin = process.stdin.take();
-        let stdout = process.stdout.take().map(BufReader::new);
+    /// Calls a tool. `timeout` overrides the server's default tool deadline,
+    /// if it has one, and
+    /// `call_id` lets the call be cancelled later with [`MCPClient::cancel_call`].
+    /// The call id is also sent as the progress token, so
+    /// `notifications/progress` for this call carry it back; each one pushes
+    /// the deadline back by the full timeout.
+    ///
+    /// Arguments are checked against the tool's `inputSchema` first; a
+    /// mismatch fails with [`MCPError::InvalidArguments`] without contacting
+    /// the server. Tools missing from the tool list, or a list that cannot be
+    /// fetched, skip the check.
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Value,
+        timeout: Option<Duration>,
+        call_id: Option<&str>,
+    ) -> Result<CallToolResult, MCPError> {
+        self.require_capability("tools")?;
+
+        println!("[MCP] Calling tool: {} with args: {}", name, arguments);
 
-        let mut client = MCPClient {
-            process,
-            stdin,
-            stdout,
-            request_id_counter: 1,
-            initialized: false,
+        // Unknown tools are left for the server to reject, and so is
+        // everything when the tool list cannot be fetched
+        let tool = match self.list_tools().await {
+            Ok(tools) => tools.into_iter().find(|tool| tool.name == name),
+            Err(e) => {
+                println!("[MCP] Calling {} without validating its arguments: {}", name, e);
+                None
+            }
         };
+        if let Some(ref tool) = tool {
+            let errors = schema::validate(&tool.input_schema, &arguments);
+            if !errors.is_empty() {
+                return Err(MCPError::InvalidArguments { tool: name.to_string(), errors });
+            }
+        }
 
-        client.initialize().await?;
-        
-        Ok(client)
-    }
+        let mut params = json!({
+            "name": name,
+            "arguments": arguments
+        });
+        if let Some(call_id) = call_id {
+            params["_meta"] = json!({ "progressToken": call_id });
+        }
 
-    async fn initialize(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let init_request = json!({
-            "jsonrpc": "2.0",
-            "id": self.next_request_id(),
-            "method": "initialize",
-            "params": {
-                "protocolVersion": "2024-11-05",
-                "capabilities": {
-                    "tools": {}
-                },
-                "clientInfo": {
-                    "name": "asetta",
-                    "version": "0.1.0"
+        let timeout = timeout.or(self.tool_timeout);
+        let response = self.send_request_with("tools/call", Some(params), timeout, call_id).await?;
+        let result: CallToolResult = parse_result("tools/call", response)?;
+
+        if self.validate_output && !result.is_error {
+            if let Some(output_schema) = tool.and_then(|tool| tool.output_schema) {
+                let errors = match result.structured_content {
+                    Some(ref structured) => schema::validate(&output_schema, structured),
+                    None => vec![ValidationError {
+                        path: "$".to_string(),
+                        expected: "structured content".to_string(),
+                        found: "nothing".to_string(),
+                    }],
+                };
+                if !errors.is_empty() {
+                    return Err(MCPError::InvalidOutput { tool: name.to_string(), errors });
                 }
             }
+        }
+
+        Ok(result)
+    }
+
+    /// Cancels the in-flight call started with `call_id`, telling the server
+    /// via `notifications/cancelled` and failing the waiting caller. A call
+    /// still waiting for a slot is dropped without ever reaching the server.
+    pub async fn cancel_call(&self, call_id: &str, reason: Option<String>) -> Result<(), MCPError> {
+        let call = self.router.in_flight_calls.lock().await.remove(call_id)
+            .ok_or_else(|| MCPError::InvalidRequest(format!("No in-flight call with id {}", call_id)))?;
+
+        // The caller is released first, so a failed notification cannot leave it waiting
+        self.router.fail(call.id, MCPError::Cancelled { reason: reason.clone() }).await;
+
+        if call.sent {
+            self.router.withdraw_requests(call.id);
+            self.send_cancelled(call.id, reason.as_deref()).await?;
+        }
+        Ok(())
+    }
+
+    /// Every resource the server offers, served from cache until the list changes.
+    pub async fn list_resources(&self) -> Result<Vec<Resource>, MCPError> {
+        self.require_capability("resources")?;
+
+        let mut resources = self.catalog.resources.lock().await;
+        if let Some(ref resources) = *resources {
+            return Ok(resources.clone());
+        }
+
+        let fetched: Vec<Resource> = self.list_all("resources/list", "resources").await?;
+        println!("[MCP] Cached {} resources", fetched.len());
+        *resources = Some(fetched.clone());
+        Ok(fetched)
+    }
+
+    // Follows `nextCursor` until the server has returned every page
+    async fn list_all<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        key: &str,
+    ) -> Result<Vec<T>, MCPError> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let params = cursor.as_ref().map(|cursor| json!({ "cursor": cursor }));
+            let response = self.send_request(method, params).await?;
+            let result = &response["result"];
+
+            if let Some(page) = result.get(key) {
+                let page = serde_json::from_value::<Vec<T>>(page.clone())
+                    .map_err(|e| MCPError::Protocol(format!("Invalid {} result: {}", method, e)))?;
+                items.extend(page);
+            }
+
+            let next = result.get("nextCursor").and_then(Value::as_str).map(String::from);
+            if next.is_none() || next == cursor {
+                break;
+            }
+            cursor = next;
+        }
+
+        Ok(items)
+    }
+
+    pub async fn read_resource(&self, uri: &str) -> Result<ReadResourceResult, MCPError> {
+        self.require_capability("resources")?;
+
+        let params = json!({
+            "uri": uri
         });
 
-        println!("[MCP] Initializing connection...");
-        let _response = self.send_request(init_request).await?;
-        
-        // Send initialized notification
-        let initialized = json!({
-            "jsonrpc": "2.0",
-            "method": "notifications/initialized"
+        let response = self.send_request("resources/read", Some(params)).await?;
+        parse_result("resources/read", response)
+    }
+
+    pub async fn list_resource_templates(&self) -> Result<Vec<ResourceTemplate>, MCPError> {
+        self.require_capability("resources")?;
+        self.list_all("resources/templates/list", "resourceTemplates").await
+    }
+
+    /// Asks the server to send `notifications/resources/updated` whenever `uri` changes.
+    pub async fn subscribe_resource(&self, uri: &str) -> Result<(), MCPError> {
+        self.require_capability("resources.subscribe")?;
+
+        let params = json!({
+            "uri": uri
         });
-        
-        self.send_notification(initialized).await?;
-        self.initialized = true;
-        println!("[MCP] Connection initialized successfully");
-        
+
+        self.send_request("resources/subscribe", Some(params)).await?;
+        self.subscriptions.lock().await.insert(uri.to_string());
+        Ok(())
+    }
+
+    pub async fn unsubscribe_resource(&self, uri: &str) -> Result<(), MCPError> {
+        self.require_capability("resources")?;
+
+        let params = json!({
+            "uri": uri
+        });
+
+        self.subscriptions.lock().await.remove(uri);
+        self.send_request("resources/unsubscribe", Some(params)).await?;
         Ok(())
     }
 
-    fn next_request_id(&mut self) -> u64 {
-        let id = self.request_id_counter;
-        self.request_id_counter += 1;
-        id
+    pub async fn list_prompts(&self) -> Result<Vec<Prompt>, MCPError> {
+        self.require_capability("prompts")?;
+        self.list_all("prompts/list", "prompts").await
     }
 
-    pub async fn list_tools(&mut self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        if !self.initialized {
-            return Err("Client not initialized".into());
-        }
+    pub async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: HashMap<String, String>,
+    ) -> Result<GetPromptResult, MCPError> {
+        self.require_capability("prompts")?;
 
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": self.next_request_id(),
-            "method": "tools/list"
+        let params = json!({
+            "name": name,
+            "arguments": arguments
         });
 
-        self.send_request(request).await
+        let response = self.send_request("prompts/get", Some(params)).await?;
+        parse_result("prompts/get", response)
     }
 
-    pub async fn call_tool(&mut self, name: &str, arguments: Value) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        if !self.initialized {
-            return Err("Client not initialized".into());
+    /// Suggests values for an argument of a prompt or resource template,
+    /// given what has been typed so far. `context` holds the arguments
+    /// already filled in, which some servers use to narrow suggestions.
+    pub async fn complete(
+        &self,
+        reference: CompletionReference,
+        argument: &str,
+        value: &str,
+        context: HashMap<String, String>,
+    ) -> Result<Completion, MCPError> {
+        self.require_capability("completions")?;
+
+        let mut params = json!({
+            "ref": reference,
+            "argument": {
+                "name": argument,
+                "value": value
+            }
+        });
+        if !context.is_empty() {
+            params["context"] = json!({ "arguments": context });
         }
 
-        println!("[MCP] Calling tool: {} with args: {}", name, arguments);
+        let response = self.send_request("completion/complete", Some(params)).await?;
+        let result: CompleteResult = parse_result("completion/complete", response)?;
+        Ok(result.completion)
+    }
 
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": self.next_request_id(),
-            "method": "tools/call",
-            "params": {
-                "name": name,
-                "arguments": arguments
-            }
+    /// The most recent log messages the server sent, oldest first.
+    pub fn logs(&self, limit: Option<usize>) -> Vec<LogEntry> {
+        self.logs.tail(limit.unwrap_or(LOG_BUFFER_CAPACITY))
+    }
+
+    /// Changes the minimum severity of log messages the server sends.
+    pub async fn set_log_level(&self, level: &str) -> Result<(), MCPError> {
+        if !LOG_LEVELS.contains(&level) {
+            return Err(MCPError::InvalidRequest(format!(
+                "Unknown log level {} (expected one of: {})",
+                level,
+                LOG_LEVELS.join(", ")
+            )));
+        }
+        self.require_capability("logging")?;
+
+        let params = json!({
+            "level": level
         });
 
-        self.send_request(request).await
+        self.send_request("logging/setLevel", Some(params)).await?;
+        *self.log_level.write().unwrap() = level.to_string();
+        Ok(())
     }
 
-    pub async fn list_resources(&mut self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        if !self.initialized {
-            return Err("Client not initialized".into());
+    // Requests the configured log level from servers that support logging,
+    // on every new session
+    async fn apply_log_level(&self) {
+        if self.require_capability("logging").is_err() {
+            return;
         }
 
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": self.next_request_id(),
-            "method": "resources/list"
+        let level = self.log_level.read().unwrap().clone();
+        let params = json!({
+            "level": level
         });
+        if let Err(e) = self.dispatch_request("logging/setLevel", Some(params), Some(self.request_timeout), None).await {
+            println!("[MCP] Failed to set log level {}: {}", level, e);
+        }
+    }
+
+    async fn send_request(&self, method: &str, params: Option<Value>) -> Result<Value, MCPError> {
+        self.send_request_with(method, params, Some(self.request_timeout), None).await
+    }
 
-        self.send_request(request).await
+    async fn send_request_with(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        timeout: Option<Duration>,
+        call_id: Option<&str>,
+    ) -> Result<Value, MCPError> {
+        self.ensure_connected().await?;
+        self.dispatch_request(method, params, timeout, call_id).await
     }
 
-    pub async fn read_resource(&mut self, uri: &str) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        if !self.initialized {
-            return Err("Client not initialized".into());
+    // A dropped socket is reopened and the session initialized again before
+    // the next request, rather than failing every call until reconnecting by hand
+    async fn ensure_connected(&self) -> Result<(), MCPError> {
+        let Transport::Socket(ref transport) = *self.transport else {
+            return Ok(());
+        };
+        if transport.is_connected() {
+            return Ok(());
         }
 
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": self.next_request_id(),
-            "method": "resources/read",
-            "params": {
+        let _guard = self.reconnect_lock.lock().await;
+        if transport.is_connected() {
+            return Ok(());
+        }
+
+        println!("[MCP] Reconnecting to {}", transport.endpoint);
+        self.initialized.store(false, Ordering::SeqCst);
+        self.catalog.invalidate().await;
+        transport.open().await?;
+        self.initialize().await?;
+
+        // Subscriptions belong to the old connection's session
+        let subscriptions: Vec<String> = self.subscriptions.lock().await.iter().cloned().collect();
+        for uri in subscriptions {
+            let params = json!({
                 "uri": uri
+            });
+            if let Err(e) = self.dispatch_request("resources/subscribe", Some(params), Some(self.request_timeout), None).await {
+                println!("[MCP] Failed to resubscribe to {}: {}", uri, e);
             }
+        }
+        self.apply_log_level().await;
+        Ok(())
+    }
+
+    // Sends a request and waits for its reply, for at most `timeout` if set
+    async fn dispatch_request(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        timeout: Option<Duration>,
+        call_id: Option<&str>,
+    ) -> Result<Value, MCPError> {
+        let id = self.next_request_id();
+        let mut request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method
         });
+        if let Some(params) = params {
+            request["params"] = params;
+        }
 
-        self.send_request(request).await
-    }
+        let (sender, mut receiver) = oneshot::channel();
+        self.router.pending.lock().await.insert(id, sender);
+        // Subscribed before sending so no progress for this request is missed
+        let mut notifications = self.router.notifications.subscribe();
 
-    async fn send_request(&mut self, request: Value) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(ref mut stdin) = self.stdin {
-            let request_str = serde_json::to_string(&request)?;
-            println!("[MCP] Sending request: {}", request_str);
-            stdin.write_all(format!("{}\n", request_str).as_bytes()).await?;
-            stdin.flush().await?;
+        if *self.router.closed.borrow() {
+            self.router.pending.lock().await.remove(&id);
+            return Err(self.closed_error().await);
+        }
+
+        // Registered before queueing for a slot, so a waiting call can be cancelled
+        if let Some(call_id) = call_id {
+            let call = InFlightCall { id, sent: false };
+            self.router.in_flight_calls.lock().await.insert(call_id.to_string(), call);
+        }
+
+        let tool_call = method == "tools/call";
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+
+        // Calls over the server's limit wait for a slot within their deadline,
+        // ending early if cancelled or the connection closes meanwhile
+        let mut unsent = None;
+        let _slot = match self.call_slots {
+            Some(ref slots) if tool_call => tokio::select! {
+                // The semaphore is never closed, so acquiring only waits
+                slot = slots.acquire() => slot.ok(),
+                reply = &mut receiver => {
+                    unsent = Some(Ok(reply));
+                    None
+                }
+                _ = sleep_until(deadline) => {
+                    unsent = Some(Err(timeout.unwrap_or_default()));
+                    None
+                }
+            },
+            _ => None,
+        };
+        let mut sent = false;
+        if unsent.is_none() {
+            match self.write_unless_cancelled(&request, id, call_id).await {
+                Ok(true) => sent = true,
+                // Cancelled just as its slot came free; the cancel fails the waiter
+                Ok(false) => {
+                    unsent = Some(tokio::select! {
+                        reply = &mut receiver => Ok(reply),
+                        _ = sleep_until(deadline) => Err(timeout.unwrap_or_default()),
+                    });
+                }
+                Err(e) => {
+                    self.router.pending.lock().await.remove(&id);
+                    self.forget_call(id, call_id).await;
+                    return Err(e);
+                }
+            }
+        }
+
+        let outcome = match unsent {
+            Some(outcome) => outcome,
+            None => self.await_reply(&mut receiver, &mut notifications, deadline, timeout, tool_call, call_id).await,
+        };
+        self.forget_call(id, call_id).await;
+
+        let response = match outcome {
+            Ok(Ok(Err(MCPError::ConnectionClosed))) | Ok(Err(_)) => {
+                return Err(self.closed_error().await);
+            }
+            Ok(Ok(reply)) => reply?,
+            Err(timeout) => {
+                self.router.pending.lock().await.remove(&id);
+                // Let the server stop working on a request nobody is waiting for
+                if sent {
+                    self.router.withdraw_requests(id);
+                    let _ = self.send_cancelled(id, Some("Request timed out")).await;
+                }
+                return Err(MCPError::Timeout { method: method.to_string(), timeout });
+            }
+        };
+
+        println!("[MCP] Response for request {}: {}", id, response);
+
+        // Check for error in response
+        if let Some(error) = response.get("error") {
+            return Err(MCPError::Rpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+                data: error.get("data").cloned(),
+            });
+        }
+
+        Ok(response)
+    }
 
-            // Read response with timeout and better error handling
-            if let Some(ref mut stdout) = self.stdout {
-                let mut response_line = String::new();
-                let bytes_read = stdout.read_line(&mut response_line).await?;
-                
-                println!("[MCP] Raw response ({} bytes): {:?}", bytes_read, response_line);
-                
-                if bytes_read == 0 {
-                    return Err("MCP server closed connection".into());
-                }
-                
-                if response_line.trim().is_empty() {
-                    return Err("Empty response from MCP server".into());
-                }
-
-                let response: Value = match serde_json::from_str(&response_line.trim()) {
-                    Ok(json) => json,
-                    Err(e) => {
-                        println!("[MCP] JSON parse error: {}", e);
-                        println!("[MCP] Raw response was: {:?}", response_line);
-                        return Err(format!("Failed to parse JSON response: {}", e).into());
+    // Waits for the reply to a sent request, or for `deadline` to pass
+    async fn await_reply(
+        &self,
+        receiver: &mut oneshot::Receiver<Result<Value, MCPError>>,
+        notifications: &mut broadcast::Receiver<MCPNotification>,
+        mut deadline: Option<tokio::time::Instant>,
+        timeout: Option<Duration>,
+        tool_call: bool,
+        call_id: Option<&str>,
+    ) -> ReplyOutcome {
+        let mut user_prompts = self.router.user_prompts.subscribe();
+        let restart = || timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        loop {
+            // A tool call may be waiting on the user, who can take longer
+            // than the call is allowed to run
+            let paused = tool_call && *user_prompts.borrow_and_update() > 0;
+            tokio::select! {
+                reply = &mut *receiver => break Ok(reply),
+                _ = sleep_until(deadline), if !paused => break Err(timeout.unwrap_or_default()),
+                // Once the user has answered the deadline starts over
+                Ok(()) = user_prompts.changed(), if tool_call => deadline = restart(),
+                // So does it whenever the server reports progress on the call
+                notification = notifications.recv(), if call_id.is_some() => {
+                    let progressed = match notification {
+                        Ok(MCPNotification::Progress { ref progress_token, .. }) => progress_token.as_str() == call_id,
+                        // Missed notifications may have included progress
+                        Err(broadcast::error::RecvError::Lagged(_)) => true,
+                        _ => false,
+                    };
+                    if progressed {
+                        deadline = restart();
                     }
-                };
-                
-                println!("[MCP] Parsed response: {}", serde_json::to_string_pretty(&response)?);
-                
-                // Check for error in response
-                if let Some(error) = response.get("error") {
-                    return Err(format!("MCP error: {}", error).into());
-                }
-                
-                Ok(response)
-            } else {
-                Err("No stdout available".into())
+                }
             }
-        } else {
-            Err("No stdin available".into())
         }
     }
 
-    async fn send_notification(&mut self, notification: Value) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(ref mut stdin) = self.stdin {
-            let notification_str = serde_json::to_string(&notification)?;
-            println!("[MCP] Sending notification: {}", notification_str);
-            stdin.write_all(format!("{}\n", notification_str).as_bytes()).await?;
-            stdin.flush().await?;
-            Ok(())
-        } else {
-            Err("No stdin available".into())
+    // Writes a request unless its call was cancelled while waiting for a
+    // slot, returning whether it was written. The calls stay locked through
+    // the write, so a cancel either lands first and nothing is sent, or after
+    // and finds the call marked sent. A call whose id a later call took over
+    // can no longer be cancelled, so it simply goes ahead.
+    async fn write_unless_cancelled(&self, request: &Value, id: u64, call_id: Option<&str>) -> Result<bool, MCPError> {
+        let Some(call_id) = call_id else {
+            self.write_message(request).await?;
+            return Ok(true);
+        };
+        let mut in_flight = self.router.in_flight_calls.lock().await;
+        match in_flight.get_mut(call_id) {
+            Some(call) if call.id == id => call.sent = true,
+            Some(_) => {}
+            None => return Ok(false),
         }
+        self.write_message(request).await?;
+        Ok(true)
     }
 
-    pub async fn shutdown(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if self.initialized {
-            let shutdown_request = json!({
-                "jsonrpc": "2.0",
-                "id": self.next_request_id(),
-                "method": "shutdown"
-            });
+    // Unregisters a finished call, unless its id has since been reused
+    async fn forget_call(&self, id: u64, call_id: Option<&str>) {
+        if let Some(call_id) = call_id {
+            let mut in_flight = self.router.in_flight_calls.lock().await;
+            if in_flight.get(call_id).is_some_and(|call| call.id == id) {
+                in_flight.remove(call_id);
+            }
+        }
+    }
 
-            let _ = self.send_request(shutdown_request).await;
+    /// Tells the server the roots changed so it fetches them again with `roots/list`.
+    pub async fn notify_roots_changed(&self) -> Result<(), MCPError> {
+        if self.handlers.roots.is_none() {
+            return Ok(());
+        }
+        self.ensure_initialized()?;
+
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/roots/list_changed"
+        });
+
+        self.send_notification(notification).await
+    }
+
+    async fn send_cancelled(&self, id: u64, reason: Option<&str>) -> Result<(), MCPError> {
+        let mut params = json!({
+            "requestId": id
+        });
+        if let Some(reason) = reason {
+            params["reason"] = json!(reason);
         }
 
-        let _ = self.process.kill().await;
+        let cancelled = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/cancelled",
+            "params": params
+        });
+
+        self.send_notification(cancelled).await
+    }
+
+    async fn send_notification(&self, notification: Value) -> Result<(), MCPError> {
+        self.write_message(&notification).await
+    }
+
+    async fn write_message(&self, message: &Value) -> Result<(), MCPError> {
+        println!("[MCP] Sending message: {}", message);
+        self.transport.send(message).await
+    }
+
+    /// Waits until the connection ends, whether the server went away or the
+    /// client was shut down, and returns why. For a local server this is
+    /// [`MCPError::ServerExited`] with its exit code and last stderr lines.
+    pub async fn wait_for_exit(&self) -> MCPError {
+        let mut closed = self.router.closed.subscribe();
+        let _ = closed.wait_for(|closed| *closed).await;
+        self.closed_error().await
+    }
+
+    /// Closes the connection. MCP has no shutdown request, so a local server
+    /// is stopped by closing its stdin, escalating to signals if it lingers.
+    pub async fn shutdown(&self) -> Result<(), MCPError> {
+        self.transport.close().await;
+        self.router.close().await;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    // A zombie waiting for a reaper is as good as gone
+    #[cfg(target_os = "linux")]
+    fn is_running(pid: i32) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => !stat.rsplit(')').next().unwrap_or_default().trim_start().starts_with('Z'),
+            Err(_) => false,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn close_kills_the_whole_process_group() {
+        // Like npx, the wrapper leaves a second process behind and reports its pid on stderr
+        let script = "sleep 300 </dev/null >/dev/null 2>&1 & echo $! >&2; exec cat";
+        let router = Arc::new(MessageRouter::new());
+        let transport = StdioTransport::spawn("sh".to_string(), vec!["-c".to_string(), script.to_string()], HashMap::new(), ResourceLimits::default(), router).unwrap();
+
+        let helper = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(line) = transport.stderr.lines.lock().unwrap().front() {
+                    break line.trim().parse::<i32>().unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(is_running(helper));
+
+        transport.close().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!is_running(helper), "helper {} outlived the server", helper);
+    }
+
+    // Reads one HTTP/1.1 request as its request line, lower-cased headers and body
+    async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Option<(String, HashMap<String, String>, String)> {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).await.ok()? == 0 {
+            return None;
+        }
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.ok()?;
+            match line.trim_end().split_once(':') {
+                Some((name, value)) => headers.insert(name.to_lowercase(), value.trim().to_string()),
+                None => break,
+            };
+        }
+        let length = headers.get("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.ok()?;
+        Some((request_line.trim_end().to_string(), headers, String::from_utf8(body).ok()?))
+    }
+
+    // Request lines the stand-in server received, each with its session id
+    // header and the JSON-RPC method it carried
+    type SeenRequests = Arc<std::sync::Mutex<Vec<(String, Option<String>, Option<String>)>>>;
+
+    // How many requests with `method` the stand-in server has received
+    fn count_method(seen: &SeenRequests, method: &str) -> usize {
+        seen.lock().unwrap().iter().filter(|(_, _, seen)| seen.as_deref() == Some(method)).count()
+    }
+
+    // Waits until the stand-in server has received a request with `method`
+    async fn wait_for_method(seen: &SeenRequests, method: &str) {
+        while count_method(seen, method) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // A stand-in Streamable HTTP server. It answers initialize with JSON and a
+    // session id and every other request with an SSE stream holding a progress
+    // notification and the reply, and records each request. It never answers
+    // a call to the `hold` tool, and fails every `notifications/cancelled`.
+    // With `hang_on_delete` it never answers the DELETE that ends the session.
+    async fn serve_streamable_http(seen: SeenRequests, hang_on_delete: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    while let Some((request_line, headers, body)) = read_request(&mut stream).await {
+                        let message: Value = serde_json::from_str(&body).unwrap_or_default();
+                        let method = message["method"].as_str().map(str::to_string);
+                        seen.lock().unwrap().push((request_line.clone(), headers.get(SESSION_ID_HEADER).cloned(), method));
+                        let held = message["method"] == "tools/call" && message["params"]["name"] == "hold";
+                        if held || (hang_on_delete && request_line.starts_with("DELETE")) {
+                            std::future::pending::<()>().await;
+                        }
+
+                        let (status, extra_headers, body) = match (request_line.split(' ').next(), message.get("id")) {
+                            (Some("GET"), _) => ("405 Method Not Allowed", String::new(), String::new()),
+                            (Some("POST"), None) if message["method"] == "notifications/cancelled" => {
+                                ("500 Internal Server Error", String::new(), String::new())
+                            }
+                            (Some("POST"), Some(id)) if message["method"] == "initialize" => (
+                                "200 OK",
+                                "content-type: application/json\r\nmcp-session-id: session-1\r\n".to_string(),
+                                json!({
+                                    "jsonrpc": "2.0",
+                                    "id": id,
+                                    "result": {
+                                        "protocolVersion": "2025-06-18",
+                                        "capabilities": { "tools": {} },
+                                        "serverInfo": { "name": "stand-in", "version": "1.0.0" }
+                                    }
+                                })
+                                .to_string(),
+                            ),
+                            (Some("POST"), Some(id)) => {
+                                let progress = json!({
+                                    "jsonrpc": "2.0",
+                                    "method": "notifications/progress",
+                                    "params": { "progressToken": "call-1", "progress": 1 }
+                                });
+                                let result = match message["method"].as_str() {
+                                    Some("tools/list") => json!({ "tools": [] }),
+                                    _ => json!({ "content": [{ "type": "text", "text": "done" }] }),
+                                };
+                                let reply = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                                (
+                                    "200 OK",
+                                    "content-type: text/event-stream\r\n".to_string(),
+                                    format!("event: message\ndata: {}\n\ndata: {}\n\n", progress, reply),
+                                )
+                            }
+                            (Some("POST"), None) => ("202 Accepted", String::new(), String::new()),
+                            _ => ("200 OK", String::new(), String::new()),
+                        };
+
+                        let response = format!(
+                            "HTTP/1.1 {}\r\n{}content-length: {}\r\n\r\n{}",
+                            status,
+                            extra_headers,
+                            body.len(),
+                            body
+                        );
+                        if stream.get_mut().write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn streamable_http_keeps_the_session_and_reads_json_and_sse_replies() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = serve_streamable_http(seen.clone(), false).await;
+
+        // initialize is answered with plain JSON
+        let client = MCPClient::new_http(url, HashMap::new(), MCPClientOptions::default()).await.unwrap();
+        assert_eq!(client.server_info().unwrap().server_info.name, "stand-in");
+
+        // tools/call is answered with an event stream
+        let mut notifications = client.subscribe_notifications();
+        let result = client.call_tool("echo", json!({}), None, Some("call-1")).await.unwrap();
+        assert!(matches!(result.content.as_slice(), [ContentBlock::Text { text, .. }] if text == "done"));
+        let progress = tokio::time::timeout(Duration::from_secs(5), notifications.recv()).await.unwrap().unwrap();
+        assert!(matches!(progress, MCPNotification::Progress { ref progress_token, .. } if progress_token == "call-1"));
+
+        client.shutdown().await.unwrap();
+
+        let seen = seen.lock().unwrap().clone();
+        assert_eq!(seen[0], ("POST /mcp HTTP/1.1".to_string(), None, Some("initialize".to_string())));
+        // Every later request carries the session the server assigned, ending with its DELETE
+        assert!(seen[1..].iter().all(|(_, session, _)| session.as_deref() == Some("session-1")), "{:?}", seen);
+        assert!(seen.iter().any(|(request_line, _, _)| request_line.starts_with("DELETE")), "{:?}", seen);
+    }
+
+    #[tokio::test]
+    async fn http_shutdown_does_not_wait_on_an_unanswered_delete() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = serve_streamable_http(seen.clone(), true).await;
+        let client = MCPClient::new_http(url, HashMap::new(), MCPClientOptions::default()).await.unwrap();
+
+        let started = Instant::now();
+        client.shutdown().await.unwrap();
+        assert!(started.elapsed() < SHUTDOWN_GRACE_PERIOD * 2, "{:?}", started.elapsed());
+        assert!(seen.lock().unwrap().iter().any(|(request_line, _, _)| request_line.starts_with("DELETE")));
+    }
+
+    #[tokio::test]
+    async fn cancel_releases_the_call_even_when_the_server_cannot_be_told() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = serve_streamable_http(seen.clone(), false).await;
+        let client = Arc::new(MCPClient::new_http(url, HashMap::new(), MCPClientOptions::default()).await.unwrap());
+
+        let call = {
+            let client = client.clone();
+            tokio::spawn(async move { client.call_tool("hold", json!({}), None, Some("held")).await })
+        };
+        wait_for_method(&seen, "tools/call").await;
+
+        // The stand-in rejects the notification, but the caller is still woken
+        assert!(client.cancel_call("held", None).await.is_err());
+        let result = tokio::time::timeout(Duration::from_secs(5), call).await.unwrap().unwrap();
+        assert!(matches!(result, Err(MCPError::Cancelled { .. })), "{:?}", result);
+        assert_eq!(count_method(&seen, "notifications/cancelled"), 1);
+    }
+
+    #[tokio::test]
+    async fn queued_calls_time_out_or_are_cancelled_without_being_sent() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = serve_streamable_http(seen.clone(), false).await;
+        let limits = ResourceLimits { max_concurrent_calls: NonZeroUsize::new(1), ..Default::default() };
+        let options = MCPClientOptions { limits, ..Default::default() };
+        let client = Arc::new(MCPClient::new_http(url, HashMap::new(), options).await.unwrap());
+
+        // The stand-in holds the first call, so it keeps the only slot
+        let _held = {
+            let client = client.clone();
+            tokio::spawn(async move { client.call_tool("hold", json!({}), None, Some("held")).await })
+        };
+        wait_for_method(&seen, "tools/call").await;
+
+        let started = Instant::now();
+        let result = client.call_tool("echo", json!({}), Some(Duration::from_millis(100)), Some("late")).await;
+        assert!(matches!(result, Err(MCPError::Timeout { .. })), "{:?}", result);
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+
+        let queued = {
+            let client = client.clone();
+            tokio::spawn(async move { client.call_tool("echo", json!({}), None, Some("queued")).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client.cancel_call("queued", Some("changed my mind".to_string())).await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), queued).await.unwrap().unwrap();
+        assert!(matches!(result, Err(MCPError::Cancelled { ref reason }) if reason.as_deref() == Some("changed my mind")), "{:?}", result);
+
+        // Neither queued call reached the server, nor did a cancellation for them
+        assert_eq!(count_method(&seen, "tools/call"), 1);
+        assert_eq!(count_method(&seen, "notifications/cancelled"), 0);
+    }
+
+    // The (event, data) pairs produced by feeding `chunks` in order
+    fn parse_events(chunks: &[&str]) -> Vec<(String, String)> {
+        let mut parser = SseParser::default();
+        chunks
+            .iter()
+            .flat_map(|chunk| parser.feed(chunk.as_bytes()))
+            .map(|event| (event.event, event.data))
+            .collect()
+    }
+
+    fn event(event: &str, data: &str) -> (String, String) {
+        (event.to_string(), data.to_string())
+    }
+
+    #[test]
+    fn tool_calls_get_a_default_deadline_unless_turned_off() {
+        let options = |tool_timeout_ms| MCPClientOptions { tool_timeout_ms, ..Default::default() };
+        assert_eq!(MCPClient::tool_timeout(&options(None)), Some(DEFAULT_TOOL_TIMEOUT));
+        assert_eq!(MCPClient::tool_timeout(&options(Some(5000))), Some(Duration::from_secs(5)));
+        assert_eq!(MCPClient::tool_timeout(&options(Some(0))), None);
+    }
+
+    #[test]
+    fn a_zero_call_limit_is_rejected() {
+        let options = serde_json::from_value::<MCPClientOptions>(json!({ "limits": { "maxConcurrentCalls": 0 } }));
+        assert!(options.is_err());
+        let options: MCPClientOptions = serde_json::from_value(json!({ "limits": { "maxConcurrentCalls": 2 } })).unwrap();
+        assert_eq!(options.limits.max_concurrent_calls.map(NonZeroUsize::get), Some(2));
+    }
+
+    #[test]
+    fn sse_parser_joins_lines_split_across_chunks() {
+        assert_eq!(parse_events(&["event: mess", "age\nda", "ta: {\"id\":1}\n", "\n"]), vec![event("message", "{\"id\":1}")]);
+        // Nothing is dispatched until the blank line arrives
+        assert!(parse_events(&["data: partial\n"]).is_empty());
+    }
+
+    #[test]
+    fn sse_parser_accepts_crlf_line_endings() {
+        assert_eq!(parse_events(&["event: endpoint\r\ndata: /messages\r\n\r\n"]), vec![event("endpoint", "/messages")]);
+        // A CRLF split between chunks is still one line ending
+        assert_eq!(parse_events(&["data: a\r", "\n\r", "\n"]), vec![event("message", "a")]);
+    }
+
+    #[test]
+    fn sse_parser_joins_multi_line_data() {
+        assert_eq!(parse_events(&["data: first\ndata: second\ndata:third\n\n"]), vec![event("message", "first\nsecond\nthird")]);
+    }
+
+    #[test]
+    fn sse_parser_skips_comments_and_empty_events() {
+        assert!(parse_events(&[": keep-alive\n\n", "event: ping\n\n"]).is_empty());
+        assert_eq!(parse_events(&[": note\ndata: x\n\n"]), vec![event("message", "x")]);
+    }
+
+    #[test]
+    fn sse_parser_defaults_the_event_name_per_event() {
+        assert_eq!(
+            parse_events(&["event: endpoint\ndata: /a\n\ndata: b\n\n"]),
+            vec![event("endpoint", "/a"), event("message", "b")]
+        );
+    }
+
+    // A stand-in for a server on the 2024-11-05 HTTP+SSE transport. The GET
+    // stream announces a relative endpoint in pieces, and replies to what is
+    // POSTed there are sent back on the stream.
+    async fn serve_legacy_sse(seen: SeenRequests) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/sse", listener.local_addr().unwrap());
+        let (replies, receiver) = mpsc::unbounded_channel::<Value>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (seen, replies, receiver) = (seen.clone(), replies.clone(), receiver.clone());
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    while let Some((request_line, _, body)) = read_request(&mut stream).await {
+                        let method = serde_json::from_str::<Value>(&body).ok().and_then(|message| message["method"].as_str().map(str::to_string));
+                        seen.lock().unwrap().push((request_line.clone(), None, method));
+
+                        if request_line.starts_with("GET /sse ") {
+                            let stream = stream.get_mut();
+                            for part in ["HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\n", ": hello\r\nevent: endpo", "int\r\ndata: /messages?sessionId=abc\r\n", "\r\n"] {
+                                stream.write_all(part.as_bytes()).await.unwrap();
+                                stream.flush().await.unwrap();
+                                tokio::time::sleep(Duration::from_millis(10)).await;
+                            }
+                            let mut receiver = receiver.lock().await;
+                            while let Some(reply) = receiver.recv().await {
+                                let event = format!("event: message\ndata: {}\n\n", reply);
+                                if stream.write_all(event.as_bytes()).await.is_err() {
+                                    break;
+                                }
+                            }
+                            return;
+                        }
+
+                        let response = if request_line.starts_with("POST /messages?sessionId=abc ") {
+                            let message: Value = serde_json::from_str(&body).unwrap();
+                            if let Some(id) = message.get("id") {
+                                let result = match message["method"].as_str() {
+                                    Some("initialize") => json!({
+                                        "protocolVersion": "2024-11-05",
+                                        "capabilities": { "tools": {} },
+                                        "serverInfo": { "name": "legacy", "version": "1.0.0" }
+                                    }),
+                                    Some("tools/list") => json!({ "tools": [] }),
+                                    _ => json!({ "content": [{ "type": "text", "text": "done" }] }),
+                                };
+                                let _ = replies.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+                            }
+                            "HTTP/1.1 202 Accepted\r\ncontent-length: 0\r\n\r\n"
+                        } else {
+                            // Includes the Streamable HTTP attempt that comes first
+                            "HTTP/1.1 405 Method Not Allowed\r\ncontent-length: 0\r\n\r\n"
+                        };
+                        if stream.get_mut().write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn a_failed_streamable_handshake_does_not_fall_back_to_sse() {
+        // A Streamable HTTP server that refuses to initialize
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let seen: SeenRequests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server_seen = seen.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let seen = server_seen.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    while let Some((request_line, _, body)) = read_request(&mut stream).await {
+                        let message: Value = serde_json::from_str(&body).unwrap_or_default();
+                        seen.lock().unwrap().push((request_line, None, message["method"].as_str().map(str::to_string)));
+                        let error = json!({
+                            "jsonrpc": "2.0",
+                            "id": message["id"],
+                            "error": { "code": INVALID_PARAMS, "message": "Unsupported protocol version" }
+                        })
+                        .to_string();
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            error.len(),
+                            error
+                        );
+                        if stream.get_mut().write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let error = MCPClient::new_http(url, HashMap::new(), MCPClientOptions::default()).await.unwrap_err();
+        assert!(matches!(error, MCPError::Rpc { code: INVALID_PARAMS, .. }), "{:?}", error);
+        let seen = seen.lock().unwrap().clone();
+        assert!(seen.iter().all(|(request_line, _, _)| request_line.starts_with("POST /mcp ")), "{:?}", seen);
+    }
+
+    #[tokio::test]
+    async fn legacy_sse_waits_for_the_endpoint_and_posts_to_it() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = serve_legacy_sse(seen.clone()).await;
+
+        let client = MCPClient::new_http(url, HashMap::new(), MCPClientOptions::default()).await.unwrap();
+        assert_eq!(client.server_info().unwrap().server_info.name, "legacy");
+        let result = client.call_tool("echo", json!({}), None, None).await.unwrap();
+        assert!(matches!(result.content.as_slice(), [ContentBlock::Text { text, .. }] if text == "done"));
+        client.shutdown().await.unwrap();
+
+        // The relative endpoint is resolved against the stream's URL
+        let seen = seen.lock().unwrap().clone();
+        assert!(seen.iter().any(|(request_line, _, _)| request_line.starts_with("POST /messages?sessionId=abc ")), "{:?}", seen);
+    }
+}
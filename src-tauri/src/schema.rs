@@ -0,0 +1,376 @@
+// A small JSON Schema checker for tool arguments and structured results.
+//
+// It covers the keywords tool schemas use in practice: type, enum, const,
+// properties, required, additionalProperties, items, the numeric, length and
+// size bounds, and allOf/anyOf/oneOf. Keywords it does not know are ignored,
+// so an unusual schema never rejects input the server would accept.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// One place where a value does not match its schema.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationError {
+    /// Where the mismatch is, e.g. `$.files[2].path`.
+    pub path: String,
+    /// What the schema requires there, e.g. `string` or `one of ["a", "b"]`.
+    pub expected: String,
+    /// What was actually found.
+    pub found: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: expected {}, found {}", self.path, self.expected, self.found)
+    }
+}
+
+/// Checks `value` against `schema`, returning every mismatch found.
+pub fn validate(schema: &Value, value: &Value) -> Vec<ValidationError> {
+    validate_at(schema, value, "$")
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<ValidationError>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        Value::Bool(false) => {
+            errors.push(error(path, "nothing", describe(value)));
+            return;
+        }
+        // `true` and anything that is not a schema accept every value
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            errors.push(error(path, types.join(" or "), describe(value)));
+            // Further keywords would only repeat the type mismatch
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            errors.push(error(path, format!("one of [{}]", allowed.join(", ")), value.to_string()));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            errors.push(error(path, constant.to_string(), value.to_string()));
+        }
+    }
+
+    match value {
+        Value::Object(object) => check_object(schema, object, path, errors),
+        Value::Array(items) => check_array(schema, items, path, errors),
+        Value::String(text) => check_string(schema, text, path, errors),
+        Value::Number(_) => check_number(schema, value.as_f64().unwrap_or_default(), path, errors),
+        _ => {}
+    }
+
+    check_combinators(schema, value, path, errors);
+}
+
+fn check_object(schema: &Map<String, Value>, object: &Map<String, Value>, path: &str, errors: &mut Vec<ValidationError>) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                errors.push(error(&property_path(path, name), "a value", "nothing"));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, value) in object {
+        let child = property_path(path, name);
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(property) => check(property, value, &child, errors),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => errors.push(error(&child, "no such property", describe(value))),
+                Some(additional) => check(additional, value, &child, errors),
+                None => {}
+            },
+        }
+    }
+
+    if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+        if (object.len() as u64) < min {
+            errors.push(error(path, format!("at least {} properties", min), format!("{}", object.len())));
+        }
+    }
+    if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+        if object.len() as u64 > max {
+            errors.push(error(path, format!("at most {} properties", max), format!("{}", object.len())));
+        }
+    }
+}
+
+fn check_array(schema: &Map<String, Value>, items: &[Value], path: &str, errors: &mut Vec<ValidationError>) {
+    match schema.get("items") {
+        // The older tuple form gives one schema per position
+        Some(Value::Array(positional)) => {
+            for (index, (item, item_schema)) in items.iter().zip(positional).enumerate() {
+                check(item_schema, item, &format!("{}[{}]", path, index), errors);
+            }
+        }
+        Some(item_schema) => {
+            for (index, item) in items.iter().enumerate() {
+                check(item_schema, item, &format!("{}[{}]", path, index), errors);
+            }
+        }
+        None => {}
+    }
+
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if (items.len() as u64) < min {
+            errors.push(error(path, format!("at least {} items", min), format!("{} items", items.len())));
+        }
+    }
+    if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+        if items.len() as u64 > max {
+            errors.push(error(path, format!("at most {} items", max), format!("{} items", items.len())));
+        }
+    }
+    if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+        let duplicate = items.iter().enumerate().any(|(index, item)| items[..index].contains(item));
+        if duplicate {
+            errors.push(error(path, "unique items", "duplicates"));
+        }
+    }
+}
+
+fn check_string(schema: &Map<String, Value>, text: &str, path: &str, errors: &mut Vec<ValidationError>) {
+    let length = text.chars().count() as u64;
+    if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+        if length < min {
+            errors.push(error(path, format!("at least {} characters", min), format!("{} characters", length)));
+        }
+    }
+    if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+        if length > max {
+            errors.push(error(path, format!("at most {} characters", max), format!("{} characters", length)));
+        }
+    }
+}
+
+fn check_number(schema: &Map<String, Value>, number: f64, path: &str, errors: &mut Vec<ValidationError>) {
+    let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+
+    if let Some(min) = bound("minimum") {
+        if number < min {
+            errors.push(error(path, format!(">= {}", min), number.to_string()));
+        }
+    }
+    if let Some(max) = bound("maximum") {
+        if number > max {
+            errors.push(error(path, format!("<= {}", max), number.to_string()));
+        }
+    }
+    if let Some(min) = bound("exclusiveMinimum") {
+        if number <= min {
+            errors.push(error(path, format!("> {}", min), number.to_string()));
+        }
+    }
+    if let Some(max) = bound("exclusiveMaximum") {
+        if number >= max {
+            errors.push(error(path, format!("< {}", max), number.to_string()));
+        }
+    }
+    if let Some(divisor) = bound("multipleOf") {
+        // Decimal divisors are inexact in binary: 19.99 / 0.01 lands just
+        // beside 1999, so the quotient only has to be close to a whole number
+        let quotient = number / divisor;
+        if divisor > 0.0 && (quotient - quotient.round()).abs() > quotient.abs().max(1.0) * 1e-9 {
+            errors.push(error(path, format!("a multiple of {}", divisor), number.to_string()));
+        }
+    }
+}
+
+fn check_combinators(schema: &Map<String, Value>, value: &Value, path: &str, errors: &mut Vec<ValidationError>) {
+    if let Some(Value::Array(all)) = schema.get("allOf") {
+        for sub in all {
+            check(sub, value, path, errors);
+        }
+    }
+
+    let matching = |subs: &[Value]| subs.iter().filter(|sub| validate_at(sub, value, path).is_empty()).count();
+
+    if let Some(Value::Array(any)) = schema.get("anyOf") {
+        if matching(any) == 0 {
+            errors.push(error(path, alternatives(any), describe(value)));
+        }
+    }
+    if let Some(Value::Array(one)) = schema.get("oneOf") {
+        match matching(one) {
+            1 => {}
+            0 => errors.push(error(path, alternatives(one), describe(value))),
+            count => errors.push(error(path, "exactly one matching schema", format!("{} matches", count))),
+        }
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    check(schema, value, path, &mut errors);
+    errors
+}
+
+// Names the alternatives of anyOf/oneOf by their types where they have one
+fn alternatives(schemas: &[Value]) -> String {
+    let names: Vec<String> = schemas
+        .iter()
+        .map(|schema| match schema.get("type") {
+            Some(Value::String(name)) => name.clone(),
+            Some(other) => other.to_string(),
+            None => "schema".to_string(),
+        })
+        .collect();
+    format!("any of [{}]", names.join(", "))
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        // An unknown type name should not reject anything
+        _ => true,
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(_) => "boolean".to_string(),
+        Value::Number(n) if n.is_f64() => "number".to_string(),
+        Value::Number(_) => "integer".to_string(),
+        Value::String(_) => "string".to_string(),
+        Value::Array(_) => "array".to_string(),
+        Value::Object(_) => "object".to_string(),
+    }
+}
+
+fn property_path(path: &str, name: &str) -> String {
+    let plain = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        format!("{}.{}", path, name)
+    } else {
+        format!("{}[{}]", path, Value::String(name.to_string()))
+    }
+}
+
+fn error(path: &str, expected: impl Into<String>, found: impl Into<String>) -> ValidationError {
+    ValidationError {
+        path: path.to_string(),
+        expected: expected.into(),
+        found: found.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn paths(errors: &[ValidationError]) -> Vec<&str> {
+        errors.iter().map(|error| error.path.as_str()).collect()
+    }
+
+    #[test]
+    fn reports_type_mismatches_with_expected_and_found() {
+        let errors = validate(&json!({ "type": "string" }), &json!(3));
+        assert_eq!(errors, vec![error("$", "string", "integer")]);
+
+        let nullable = json!({ "type": ["string", "null"] });
+        assert!(validate(&nullable, &json!(null)).is_empty());
+        assert_eq!(validate(&nullable, &json!(true))[0].expected, "string or null");
+    }
+
+    #[test]
+    fn reports_every_missing_required_property() {
+        let schema = json!({ "type": "object", "required": ["chain", "amount"] });
+        let errors = validate(&schema, &json!({ "chain": "base" }));
+        assert_eq!(errors, vec![error("$.amount", "a value", "nothing")]);
+        assert_eq!(paths(&validate(&schema, &json!({}))), vec!["$.chain", "$.amount"]);
+    }
+
+    #[test]
+    fn checks_additional_properties() {
+        let closed = json!({ "properties": { "name": { "type": "string" } }, "additionalProperties": false });
+        assert!(validate(&closed, &json!({ "name": "a" })).is_empty());
+        assert_eq!(validate(&closed, &json!({ "name": "a", "extra": 1 })), vec![error("$.extra", "no such property", "integer")]);
+
+        let typed = json!({ "additionalProperties": { "type": "number" } });
+        assert!(validate(&typed, &json!({ "a": 1.5 })).is_empty());
+        assert_eq!(paths(&validate(&typed, &json!({ "a": "x" }))), vec!["$.a"]);
+
+        // Without the keyword anything goes
+        assert!(validate(&json!({ "properties": {} }), &json!({ "extra": 1 })).is_empty());
+    }
+
+    #[test]
+    fn any_of_needs_one_match_and_one_of_exactly_one() {
+        let any_of = json!({ "anyOf": [{ "type": "string" }, { "type": "integer" }] });
+        assert!(validate(&any_of, &json!("a")).is_empty());
+        assert!(validate(&any_of, &json!(2)).is_empty());
+        assert_eq!(validate(&any_of, &json!(true)), vec![error("$", "any of [string, integer]", "boolean")]);
+
+        let one_of = json!({ "oneOf": [{ "type": "number" }, { "type": "integer" }] });
+        assert!(validate(&one_of, &json!(1.5)).is_empty());
+        assert_eq!(validate(&one_of, &json!(2)), vec![error("$", "exactly one matching schema", "2 matches")]);
+        assert_eq!(validate(&one_of, &json!("a")).len(), 1);
+    }
+
+    #[test]
+    fn integers_are_whole_numbers_of_any_representation() {
+        let integer = json!({ "type": "integer" });
+        assert!(validate(&integer, &json!(7)).is_empty());
+        assert!(validate(&integer, &json!(-7)).is_empty());
+        assert!(validate(&integer, &json!(7.0)).is_empty());
+        assert_eq!(validate(&integer, &json!(7.5)), vec![error("$", "integer", "number")]);
+
+        // Every integer is also a number
+        assert!(validate(&json!({ "type": "number" }), &json!(7)).is_empty());
+    }
+
+    #[test]
+    fn quotes_property_names_that_are_not_identifiers() {
+        let schema = json!({
+            "properties": {
+                "files": { "items": { "properties": { "file mode": { "type": "string" } } } },
+                "2fa": { "type": "string" },
+                "": { "type": "string" }
+            }
+        });
+        let value = json!({ "files": [{}, { "file mode": 1 }], "2fa": 1, "": 1 });
+        let errors = validate(&schema, &value);
+        let mut found = paths(&errors);
+        found.sort_unstable();
+        assert_eq!(found, vec![r#"$.files[1]["file mode"]"#, r#"$[""]"#, r#"$["2fa"]"#]);
+    }
+
+    #[test]
+    fn multiple_of_tolerates_decimal_rounding() {
+        let cents = json!({ "type": "number", "multipleOf": 0.01 });
+        for price in [19.99, 0.07, 0.1, 1234.56, 0.0, -3.3] {
+            assert!(validate(&cents, &json!(price)).is_empty(), "{} was rejected", price);
+        }
+        assert_eq!(validate(&cents, &json!(0.015)), vec![error("$", "a multiple of 0.01", "0.015")]);
+
+        let fives = json!({ "multipleOf": 5 });
+        assert!(validate(&fives, &json!(1_000_000_000_000_i64)).is_empty());
+        assert_eq!(validate(&fives, &json!(12)).len(), 1);
+    }
+}
@@ -7,6 +7,7 @@ import { WelcomeScreen } from './components/setup/WelcomeScreen';
 import { SettingsModal } from './components/setup/SettingsModal';
 import { MCPManagerModal } from './components/MCPManagerModal';
 import { AddAgentModal } from './components/AddAgentModal'; 
+import { MCPElicitationModal } from './components/MCPElicitationModal';
 import { OnboardingManager } from './components/auth/AuthComponents';
 import { authService } from './services/auth';
 import { agentService } from './services/agentService';
@@ -295,6 +296,9 @@ function App() {
         onAgentCreated={handleAgentCreated}
       />
 
+      {/* Input requested by an MCP server mid-call */}
+      <MCPElicitationModal />
+
     </div>
   );
 }
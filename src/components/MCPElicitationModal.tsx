@@ -0,0 +1,181 @@
+import React, { useState, useEffect } from 'react';
+import { MessageSquare, AlertCircle, X } from 'lucide-react';
+import { mcpService } from '../services/mcpService';
+import { MCPElicitationRequestEvent, MCPServerEvent } from '../types/mcp';
+
+// One field of an elicitation's requestedSchema; the spec only allows flat primitives
+interface ElicitationField {
+  type?: 'string' | 'number' | 'integer' | 'boolean';
+  title?: string;
+  description?: string;
+  enum?: string[];
+  enumNames?: string[];
+  default?: any;
+}
+
+const initialValues = (request: MCPElicitationRequestEvent): Record<string, any> => {
+  const properties: Record<string, ElicitationField> = request.requestedSchema?.properties ?? {};
+  const values: Record<string, any> = {};
+  for (const [name, field] of Object.entries(properties)) {
+    if (field.default !== undefined) {
+      values[name] = field.default;
+    } else if (field.type === 'boolean') {
+      values[name] = false;
+    }
+  }
+  return values;
+};
+
+// Shows input requests that MCP servers send mid-call, one at a time
+export const MCPElicitationModal: React.FC = () => {
+  const [requests, setRequests] = useState<MCPElicitationRequestEvent[]>(() => mcpService.getPendingElicitations());
+  const [values, setValues] = useState<Record<string, any>>({});
+  const [error, setError] = useState<string | null>(null);
+
+  const current = requests[0];
+
+  useEffect(() => {
+    const onRequested = (event: MCPServerEvent) => {
+      setRequests(prev => [...prev, event.data]);
+    };
+    // The call ended or timed out before the user answered
+    const onWithdrawn = (event: MCPServerEvent) => {
+      setRequests(prev => prev.filter(request => request.request_id !== event.data.request_id));
+    };
+    mcpService.addEventListener('elicitationRequested', onRequested);
+    mcpService.addEventListener('elicitationWithdrawn', onWithdrawn);
+    return () => {
+      mcpService.removeEventListener('elicitationRequested', onRequested);
+      mcpService.removeEventListener('elicitationWithdrawn', onWithdrawn);
+    };
+  }, []);
+
+  useEffect(() => {
+    setValues(current ? initialValues(current) : {});
+    setError(null);
+  }, [current?.request_id]);
+
+  if (!current) return null;
+
+  const properties: Record<string, ElicitationField> = current.requestedSchema?.properties ?? {};
+  const required: string[] = current.requestedSchema?.required ?? [];
+
+  const finish = async (action: 'accept' | 'decline' | 'cancel') => {
+    let content: Record<string, any> | undefined;
+    if (action === 'accept') {
+      const missing = required.filter(name => values[name] === undefined || values[name] === '');
+      if (missing.length > 0) {
+        setError(`Please fill in: ${missing.map(name => properties[name]?.title ?? name).join(', ')}`);
+        return;
+      }
+      content = values;
+    }
+
+    setRequests(prev => prev.filter(request => request.request_id !== current.request_id));
+    try {
+      await mcpService.answerElicitation(current.request_id, action, content);
+    } catch (error: any) {
+      console.error('Failed to answer MCP input request:', error);
+    }
+  };
+
+  const setValue = (name: string, field: ElicitationField, raw: string | boolean) => {
+    let value: any = raw;
+    if ((field.type === 'number' || field.type === 'integer') && typeof raw === 'string') {
+      value = raw === '' ? undefined : Number(raw);
+    }
+    setValues(prev => ({ ...prev, [name]: value }));
+  };
+
+  const inputClass = "w-full bg-slate-700 border border-slate-600 rounded-lg px-3 py-2 text-white placeholder-slate-400 focus:border-blue-500 focus:ring-1 focus:ring-blue-500";
+
+  return (
+    <div className="fixed inset-0 bg-black/50 flex items-center justify-center p-4 z-50">
+      <div className="bg-slate-800 border border-slate-700 rounded-xl p-6 w-full max-w-lg max-h-[90vh] overflow-y-auto">
+        <div className="flex items-center justify-between mb-4">
+          <div className="flex items-center gap-3">
+            <MessageSquare className="w-5 h-5 text-blue-400" />
+            <div>
+              <h2 className="text-lg font-semibold text-white">Input requested</h2>
+              <p className="text-slate-400 text-sm">From MCP server {current.server_name}</p>
+            </div>
+          </div>
+          <button
+            onClick={() => finish('cancel')}
+            className="p-2 hover:bg-slate-700 rounded-lg text-slate-400 hover:text-white transition-colors"
+          >
+            <X className="w-5 h-5" />
+          </button>
+        </div>
+
+        <p className="text-slate-200 text-sm mb-4 whitespace-pre-wrap">{current.message}</p>
+
+        <div className="space-y-4">
+          {Object.entries(properties).map(([name, field]) => (
+            <div key={name}>
+              {field.type === 'boolean' ? (
+                <label className="flex items-center gap-2 text-sm text-slate-300">
+                  <input
+                    type="checkbox"
+                    checked={!!values[name]}
+                    onChange={(e) => setValue(name, field, e.target.checked)}
+                  />
+                  {field.title ?? name}
+                </label>
+              ) : (
+                <>
+                  <label className="block text-sm font-medium text-slate-300 mb-2">
+                    {field.title ?? name}{required.includes(name) && ' *'}
+                  </label>
+                  {field.enum ? (
+                    <select
+                      value={values[name] ?? ''}
+                      onChange={(e) => setValue(name, field, e.target.value)}
+                      className={inputClass}
+                    >
+                      <option value="">Select…</option>
+                      {field.enum.map((option, index) => (
+                        <option key={option} value={option}>{field.enumNames?.[index] ?? option}</option>
+                      ))}
+                    </select>
+                  ) : (
+                    <input
+                      type={field.type === 'number' || field.type === 'integer' ? 'number' : 'text'}
+                      step={field.type === 'integer' ? 1 : undefined}
+                      value={values[name] ?? ''}
+                      onChange={(e) => setValue(name, field, e.target.value)}
+                      className={inputClass}
+                    />
+                  )}
+                </>
+              )}
+              {field.description && <p className="text-slate-500 text-xs mt-1">{field.description}</p>}
+            </div>
+          ))}
+        </div>
+
+        {error && (
+          <div className="flex items-center gap-2 p-3 mt-4 bg-red-900/50 border border-red-500 rounded-lg">
+            <AlertCircle className="w-4 h-4 text-red-400 flex-shrink-0" />
+            <span className="text-red-200 text-sm">{error}</span>
+          </div>
+        )}
+
+        <div className="flex justify-end gap-3 pt-4 mt-4 border-t border-slate-700">
+          <button
+            onClick={() => finish('decline')}
+            className="px-4 py-2 bg-slate-700 hover:bg-slate-600 text-white rounded-lg transition-colors"
+          >
+            Decline
+          </button>
+          <button
+            onClick={() => finish('accept')}
+            className="px-6 py-2 bg-blue-600 hover:bg-blue-700 text-white rounded-lg transition-colors"
+          >
+            Submit
+          </button>
+        </div>
+      </div>
+    </div>
+  );
+};
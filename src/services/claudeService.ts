@@ -1,11 +1,13 @@
 import {
   BedrockRuntimeClient,
+  InvokeModelCommand,
   InvokeModelWithResponseStreamCommand,
 } from "@aws-sdk/client-bedrock-runtime";
 import { mcpService } from './mcpService';
 import { agentChatService } from './agentChatService';
 import { Logger } from '../utils/logger';
 import { Agent } from '../types/agent';
+import { MCPSamplingRequestEvent, MCPSamplingResult } from '../types/mcp';
 
 export interface AIResponse {
   content: string;
@@ -40,6 +42,9 @@ export class ClaudeService {
     // Register with AgentChatService
     agentChatService.registerClaudeService(this);
 
+    // Answer sampling requests from MCP servers with this model
+    mcpService.setSamplingModel(this);
+
   }
 
   private getAwsConfig(): { awsAccessKey: string; awsSecretKey: string; awsRegion: string } {
@@ -307,6 +312,61 @@ export class ClaudeService {
     }
   }
 
+  // Runs a sampling request from an MCP server, without tools or chat history
+  async createMessage(request: MCPSamplingRequestEvent): Promise<MCPSamplingResult> {
+    const messages = request.messages.map(message => {
+      const content = message.content;
+      switch (content?.type) {
+        case 'text':
+          return { role: message.role, content: [{ type: 'text', text: content.text }] };
+        case 'image':
+          return {
+            role: message.role,
+            content: [{ type: 'image', source: { type: 'base64', media_type: content.mimeType, data: content.data } }]
+          };
+        default:
+          throw new Error(`Unsupported sampling content: ${content?.type}`);
+      }
+    });
+
+    const payload = {
+      anthropic_version: "bedrock-2023-05-31",
+      max_tokens: request.maxTokens,
+      system: request.systemPrompt,
+      temperature: request.temperature,
+      stop_sequences: request.stopSequences,
+      messages,
+    };
+
+    const command = new InvokeModelCommand({
+      contentType: "application/json",
+      body: JSON.stringify(payload),
+      modelId: "apac.anthropic.claude-sonnet-4-20250514-v1:0",
+    });
+
+    const response = await this.client.send(command);
+    const body = JSON.parse(new TextDecoder().decode(response.body));
+    const text = (body.content || [])
+      .filter((block: any) => block.type === 'text')
+      .map((block: any) => block.text)
+      .join('');
+
+    this.logger.info('claude', `Completed sampling request from ${request.server_name}`, { stopReason: body.stop_reason });
+
+    // MCP names stop reasons in camelCase
+    const stopReasons: Record<string, string> = {
+      end_turn: 'endTurn',
+      stop_sequence: 'stopSequence',
+      max_tokens: 'maxTokens'
+    };
+    return {
+      role: 'assistant',
+      content: { type: 'text', text },
+      model: "apac.anthropic.claude-sonnet-4-20250514-v1:0",
+      stopReason: stopReasons[body.stop_reason] ?? body.stop_reason
+    };
+  }
+
   // Set active agent for tool filtering
   setActiveAgent(agent: Agent | null): void {
     this.currentAgent = agent;
@@ -356,25 +416,32 @@ export class ClaudeService {
 
       console.log('MCP tool result:', result);
 
-      // Extract text content from the result
-      if (result && result.result && result.result.content) {
-        const textContent = result.result.content
-          .filter((item: any) => item.type === 'text')
-          .map((item: any) => item.text)
-          .join('\n');
-        return textContent || 'Tool executed successfully';
+      const textContent = result.content
+        .map((item) => {
+          switch (item.type) {
+            case 'text':
+              return item.text;
+            case 'resource_link':
+              return `[resource: ${item.uri}]`;
+            case 'resource':
+              return 'text' in item.resource ? item.resource.text : `[resource: ${item.resource.uri}]`;
+            default:
+              return `[${item.type}: ${item.mimeType}]`;
+          }
+        })
+        .join('\n');
+
+      if (result.isError) {
+        throw new Error(textContent || 'Tool reported an error');
       }
 
-      // Handle direct content array (some MCP servers return this format)
-      if (result && Array.isArray(result.content)) {
-        const textContent = result.content
-          .filter((item: any) => item.type === 'text')
-          .map((item: any) => item.text)
-          .join('\n');
-        return textContent || 'Tool executed successfully';
+      if (textContent) {
+        return textContent;
       }
 
-      return JSON.stringify(result, null, 2);
+      return result.structuredContent !== undefined
+        ? JSON.stringify(result.structuredContent, null, 2)
+        : 'Tool executed successfully';
     } catch (error) {
       console.error(`Failed to execute MCP tool ${toolName}:`, error);
       throw new Error(`Failed to execute tool: ${error}`);
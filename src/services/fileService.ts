@@ -1,6 +1,7 @@
 import { invoke } from '@tauri-apps/api/core';
 import { open } from '@tauri-apps/plugin-dialog';
 import { FileInfo } from '../types/files';
+import { toCommandError } from '../utils/commandError';
 
 export class TauriFileService {
   /**
@@ -11,7 +12,7 @@ export class TauriFileService {
       return await invoke<FileInfo[]>('read_directory', { path });
     } catch (error) {
       console.error('Failed to read directory:', error);
-      throw new Error(`Failed to read directory: ${error}`);
+      throw toCommandError(error);
     }
   }
 
@@ -23,7 +24,7 @@ export class TauriFileService {
       return await invoke<string>('read_file_content', { path });
     } catch (error) {
       console.error('Failed to read file:', error);
-      throw new Error(`Failed to read file: ${error}`);
+      throw toCommandError(error);
     }
   }
 
@@ -35,7 +36,7 @@ export class TauriFileService {
       await invoke('write_file_content', { path, content });
     } catch (error) {
       console.error('Failed to write file:', error);
-      throw new Error(`Failed to write file: ${error}`);
+      throw toCommandError(error);
     }
   }
 
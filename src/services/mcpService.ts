@@ -1,20 +1,191 @@
 import { tauriMCPService } from './tauriMCPService';
-import { MCPServerConfig, MCPServerInstance, MCPTool, MCPResource, MCPServerEvent } from '../types/mcp';
+import { MCPServerConfig, MCPServerInstance, MCPTool, MCPToolDefinition, MCPResource, MCPServerEvent, MCPCallToolResult, MCPReadResourceResult, MCPSamplingRequestEvent, MCPSamplingResult, MCPElicitationRequestEvent, MCPElicitationAction } from '../types/mcp';
 import { Logger } from '../utils/logger';
 
+// The model that answers sampling requests from servers
+export interface MCPSamplingModel {
+  createMessage(request: MCPSamplingRequestEvent): Promise<MCPSamplingResult>;
+}
+
 export class MCPService {
   private servers: Map<string, MCPServerInstance> = new Map();
   private eventListeners: Map<string, ((event: MCPServerEvent) => void)[]> = new Map();
   private workspaceRoot: string | null = null;
+  private samplingModel: MCPSamplingModel | null = null;
+  private pendingElicitations: Map<string, MCPElicitationRequestEvent> = new Map();
   private logger = Logger.getInstance();
   private storage_key = 'asetta-mcp-servers';
 
   constructor() {
     this.loadServersFromStorage();
     this.setupDefaultServers();
+    this.forwardServerLogs();
+    this.watchServerStatus();
+    this.handleSamplingRequests();
+    this.handleElicitationRequests();
     this.logger.info('mcp', 'MCP Service initialized with Tauri integration');
   }
 
+  // Show each server's log messages and stderr in the logs panel under its own category
+  private forwardServerLogs(): void {
+    tauriMCPService.onNotification('mcp:message', (event) => {
+      const category = `mcp:${event.server_name}`;
+      const message = typeof event.data === 'string' ? event.data : JSON.stringify(event.data);
+      const text = event.logger ? `[${event.logger}] ${message}` : message;
+      switch (event.level) {
+        case 'debug':
+        case 'info':
+        case 'notice':
+          this.logger.info(category, text);
+          break;
+        case 'warning':
+          this.logger.warn(category, text);
+          break;
+        default:
+          this.logger.error(category, text, { level: event.level });
+      }
+    }).catch(err => {
+      console.error('Failed to listen for MCP server logs:', err);
+    });
+
+    tauriMCPService.onNotification('mcp:stderr', (event) => {
+      this.logger.info(`mcp:${event.server_name}`, `[stderr] ${event.line}`);
+    }).catch(err => {
+      console.error('Failed to listen for MCP server stderr:', err);
+    });
+  }
+
+  // Follow supervised servers as they crash and restart
+  private watchServerStatus(): void {
+    tauriMCPService.onServerStatus((event) => {
+      const server = this.servers.get(event.server_name);
+      if (!server) {
+        return;
+      }
+
+      switch (event.status) {
+        case 'exited':
+          this.logger.error('mcp', `MCP server ${event.server_name} exited`, {
+            exitCode: event.exit_code,
+            limit: event.limit,
+            stderr: event.stderr,
+            error: event.error?.message
+          });
+          server.error = event.error?.message
+            ?? (event.limit && `Exceeded its ${event.limit.replace('_', ' ')} limit`)
+            ?? event.stderr
+            ?? `Exited with code ${event.exit_code ?? 'unknown'}`;
+          return;
+        case 'restarting':
+          this.logger.warn('mcp', `Restarting MCP server ${event.server_name} (attempt ${event.attempt})`);
+          server.status = 'starting';
+          break;
+        case 'running':
+          this.logger.info('mcp', `MCP server ${event.server_name} restarted`);
+          server.status = 'running';
+          server.error = undefined;
+          server.lastStarted = new Date();
+          break;
+        case 'stopped':
+          server.status = 'error';
+          break;
+      }
+      this.emit('serverStatusChanged', { serverName: event.server_name, status: server.status, error: server.error });
+    }).catch(err => {
+      console.error('Failed to listen for MCP server status:', err);
+    });
+
+    tauriMCPService.onNotification('mcp:health', (event) => {
+      const details = { latencyMs: event.latency_ms, failures: event.consecutive_failures, error: event.last_error };
+      if (event.state === 'healthy') {
+        this.logger.info('mcp', `MCP server ${event.server_name} is healthy again`, details);
+      } else {
+        this.logger.warn('mcp', `MCP server ${event.server_name} is ${event.state}`, details);
+      }
+    }).catch(err => {
+      console.error('Failed to listen for MCP server health:', err);
+    });
+  }
+
+  setSamplingModel(model: MCPSamplingModel | null) {
+    this.samplingModel = model;
+  }
+
+  // Servers may ask the app's model for a completion; the user approves each request first
+  private handleSamplingRequests(): void {
+    tauriMCPService.onSamplingApproval((event) => {
+      if (!this.samplingModel) {
+        this.logger.warn('mcp', `Rejected sampling request from ${event.server_name}: no model is configured`);
+        tauriMCPService.respondSamplingApproval(event.request_id, false);
+        return;
+      }
+
+      const prompt = event.messages
+        .map(message => message.content?.type === 'text' ? message.content.text : `[${message.content?.type}]`)
+        .join('\n');
+      const approved = confirm(
+        `MCP server "${event.server_name}" wants to run the model (up to ${event.maxTokens} tokens) on:\n\n` +
+        `${prompt.length > 500 ? `${prompt.slice(0, 500)}…` : prompt}\n\nAllow it?`
+      );
+      this.logger.info('mcp', `Sampling request from ${event.server_name} ${approved ? 'approved' : 'rejected'}`);
+      tauriMCPService.respondSamplingApproval(event.request_id, approved);
+    }).catch(err => {
+      console.error('Failed to listen for MCP sampling approvals:', err);
+    });
+
+    tauriMCPService.onSamplingRequest(async (event) => {
+      if (!this.samplingModel) {
+        await tauriMCPService.respondSampling(event.request_id, null, 'No model is configured');
+        return;
+      }
+
+      try {
+        const result = await this.samplingModel.createMessage(event);
+        await tauriMCPService.respondSampling(event.request_id, result);
+      } catch (error: any) {
+        this.logger.error('mcp', `Sampling request from ${event.server_name} failed`, { error: error.message });
+        await tauriMCPService.respondSampling(event.request_id, null, error.message);
+      }
+    }).catch(err => {
+      console.error('Failed to listen for MCP sampling requests:', err);
+    });
+  }
+
+  // Servers may ask the user for input mid-call; MCPElicitationModal shows each
+  // request and closes it again if the server withdraws it first
+  private handleElicitationRequests(): void {
+    tauriMCPService.onElicitationRequest((event) => {
+      this.logger.info('mcp', `MCP server ${event.server_name} is asking for input`, { message: event.message });
+      this.pendingElicitations.set(event.request_id, event);
+      this.emit('elicitationRequested', event);
+    }).catch(err => {
+      console.error('Failed to listen for MCP elicitation requests:', err);
+    });
+
+    tauriMCPService.onHostRequestWithdrawn((event) => {
+      if (this.pendingElicitations.delete(event.request_id)) {
+        this.logger.info('mcp', `MCP server ${event.server_name} no longer needs input`);
+        this.emit('elicitationWithdrawn', event);
+      }
+    }).catch(err => {
+      console.error('Failed to listen for withdrawn MCP requests:', err);
+    });
+  }
+
+  getPendingElicitations(): MCPElicitationRequestEvent[] {
+    return Array.from(this.pendingElicitations.values());
+  }
+
+  async answerElicitation(requestId: string, action: MCPElicitationAction, content?: Record<string, any>): Promise<void> {
+    const request = this.pendingElicitations.get(requestId);
+    if (!request) {
+      return;
+    }
+    this.pendingElicitations.delete(requestId);
+    this.logger.info('mcp', `Answered input request from ${request.server_name}: ${action}`);
+    await tauriMCPService.respondElicitation(requestId, action, content);
+  }
+
   // Load servers from localStorage
   private loadServersFromStorage(): void {
     try {
@@ -54,19 +225,18 @@ export class MCPService {
   setWorkspaceRoot(path: string | null) {
     this.logger.info('mcp', `Setting current folder to: ${path}`);
     this.workspaceRoot = path;
+
+    // Running servers are told about the new roots instead of being restarted
+    tauriMCPService.setWorkspaceRoots(path ? [path] : []).catch(err => {
+      this.logger.error('mcp', `Failed to update workspace roots: ${err.message}`);
+    });
+
     const fsServer = this.servers.get('filesystem');
     if (fsServer && path) {
       this.logger.info('mcp', `Updating filesystem server args with new folder: ${path}`);
       fsServer.config.args = ['-y', '@modelcontextprotocol/server-filesystem', path];
 
-      if (fsServer.status === 'running') {
-        this.logger.info('mcp', `Restarting filesystem server with new folder: ${path}`);
-        this.restartServer('filesystem').then(() => {
-          this.logger.info('mcp', `Filesystem server restarted successfully with folder: ${path}`);
-        }).catch(err => {
-          this.logger.error('mcp', `Failed to restart filesystem server: ${err.message}`);
-        });
-      } else {
+      if (fsServer.status !== 'running') {
         this.logger.info('mcp', `Starting filesystem server with folder: ${path}`);
         this.startServer('filesystem').then(() => {
           this.logger.info('mcp', `Filesystem server started successfully with folder: ${path}`);
@@ -156,12 +326,19 @@ export class MCPService {
         serverName,
         server.config.command,
         server.config.args,
-        server.config.env || {}
+        server.config.env || {},
+        {
+          restart: server.config.restart,
+          limits: server.config.limits,
+          sampling: server.config.sampling ?? true,
+          elicitation: server.config.elicitation ?? true,
+          roots: server.config.roots ?? server.config.category === 'filesystem'
+        }
       );
 
       // Load real tools from the server
-      const toolsResponse = await tauriMCPService.listTools(serverName);
-      server.tools = this.formatMCPTools(toolsResponse);
+      const tools = await tauriMCPService.listTools(serverName);
+      server.tools = this.formatMCPTools(tools);
       this.logger.info('mcp', `Loaded ${server.tools.length} tools from ${serverName}`, {
         tools: server.tools.map(t => t.name)
       });
@@ -169,8 +346,8 @@ export class MCPService {
       // Only try to load resources if server supports it (skip for filesystem)
       if (serverName !== 'filesystem') {
         try {
-          const resourcesResponse = await tauriMCPService.listResources(serverName);
-          server.resources = this.formatMCPResources(resourcesResponse);
+          const resources = await tauriMCPService.listResources(serverName);
+          server.resources = this.formatMCPResources(resources);
         } catch (error) {
           this.logger.warn('mcp', `Server ${serverName} doesn't support resources`, { error });
           server.resources = [];
@@ -302,24 +479,16 @@ export class MCPService {
     this.emit('serverConfigUpdated', { serverName, config: server.config });
   }
 
-  private formatMCPTools(toolsResponse: any): MCPTool[] {
-    if (!toolsResponse || !toolsResponse.result || !toolsResponse.result.tools) {
-      return [];
-    }
-
-    return toolsResponse.result.tools.map((tool: any) => ({
+  private formatMCPTools(tools: MCPToolDefinition[]): MCPTool[] {
+    return tools.map(tool => ({
       name: tool.name,
-      description: tool.description || tool.name,
+      description: tool.description || tool.title || tool.name,
       inputSchema: tool.inputSchema || {}
     }));
   }
 
-  private formatMCPResources(resourcesResponse: any): MCPResource[] {
-    if (!resourcesResponse || !resourcesResponse.result || !resourcesResponse.result.resources) {
-      return [];
-    }
-
-    return resourcesResponse.result.resources.map((resource: any) => ({
+  private formatMCPResources(resources: MCPResource[]): MCPResource[] {
+    return resources.map(resource => ({
       uri: resource.uri,
       name: resource.name || resource.uri,
       description: resource.description,
@@ -327,7 +496,7 @@ export class MCPService {
     }));
   }
 
-  async callTool(serverName: string, toolName: string, args: Record<string, any>): Promise<any> {
+  async callTool(serverName: string, toolName: string, args: Record<string, any>): Promise<MCPCallToolResult> {
     const server = this.servers.get(serverName);
     if (!server || server.status !== 'running') {
       throw new Error(`Server ${serverName} is not running`);
@@ -338,18 +507,33 @@ export class MCPService {
       throw new Error(`Tool ${toolName} not found on server ${serverName}`);
     }
 
+    const callId = crypto.randomUUID();
+    const unlisten = await tauriMCPService.onToolProgress(callId, (event) => {
+      this.emit('toolProgress', {
+        serverName,
+        toolName,
+        callId,
+        progress: event.progress,
+        total: event.total,
+        message: event.message
+      });
+    });
+
     try {
-      const result = await tauriMCPService.callTool(serverName, toolName, args);
-      this.emit('toolCalled', { serverName, toolName, arguments: args, result });
+      this.emit('toolCallStarted', { serverName, toolName, arguments: args, callId });
+      const result = await tauriMCPService.callTool(serverName, toolName, args, { callId });
+      this.emit('toolCalled', { serverName, toolName, arguments: args, result, callId });
       return result;
     } catch (error) {
       console.error(`Failed to call tool ${toolName} on server ${serverName}:`, error);
-      this.emit('toolError', { serverName, toolName, arguments: args, error });
+      this.emit('toolError', { serverName, toolName, arguments: args, error, callId });
       throw error;
+    } finally {
+      unlisten();
     }
   }
 
-  async readResource(serverName: string, uri: string): Promise<any> {
+  async readResource(serverName: string, uri: string): Promise<MCPReadResourceResult> {
     const server = this.servers.get(serverName);
     if (!server || server.status !== 'running') {
       throw new Error(`Server ${serverName} is not running`);
@@ -1,14 +1,39 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
+import { MCPCallToolResult, MCPClientOptions, MCPConnectedServer, MCPCompletion, MCPCompletionReference, MCPElicitationAction, MCPElicitationRequestEvent, MCPGetPromptResult, MCPHostRequestWithdrawnEvent, MCPLogEntry, MCPLogLevel, MCPNotificationEvent, MCPNotificationEventName, MCPProgressEvent, MCPPrompt, MCPReadResourceResult, MCPResource, MCPResourceTemplate, MCPSamplingRequestEvent, MCPSamplingResult, MCPServerInfo, MCPServerStatusEvent, MCPToolCallOptions, MCPToolDefinition } from '../types/mcp';
+import { toCommandError } from '../utils/commandError';
 
 export interface TauriMCPService {
   connectServer(serverName: string, command: string, args: string[]): Promise<string>;
-  connectServerWithEnv(serverName: string, command: string, args: string[], env: Record<string, string>): Promise<string>;
+  connectServerWithEnv(serverName: string, command: string, args: string[], env: Record<string, string>, options?: MCPClientOptions): Promise<string>;
+  connectServerHttp(serverName: string, url: string, headers: Record<string, string>, options?: MCPClientOptions): Promise<string>;
   disconnectServer(serverName: string): Promise<string>;
-  listConnectedServers(): Promise<string[]>;
-  listTools(serverName: string): Promise<any>;
-  callTool(serverName: string, toolName: string, args: any): Promise<any>;
-  listResources(serverName: string): Promise<any>;
-  readResource(serverName: string, uri: string): Promise<any>;
+  listConnectedServers(): Promise<MCPConnectedServer[]>;
+  getServerInfo(serverName: string): Promise<MCPServerInfo>;
+  getServerLogs(serverName: string, limit?: number): Promise<MCPLogEntry[]>;
+  setLogLevel(serverName: string, level: MCPLogLevel): Promise<void>;
+  listTools(serverName: string): Promise<MCPToolDefinition[]>;
+  callTool(serverName: string, toolName: string, args: any, options?: MCPToolCallOptions): Promise<MCPCallToolResult>;
+  cancelToolCall(serverName: string, callId: string, reason?: string): Promise<string>;
+  listResources(serverName: string): Promise<MCPResource[]>;
+  readResource(serverName: string, uri: string): Promise<MCPReadResourceResult>;
+  listResourceTemplates(serverName: string): Promise<MCPResourceTemplate[]>;
+  subscribeResource(serverName: string, uri: string): Promise<void>;
+  unsubscribeResource(serverName: string, uri: string): Promise<void>;
+  listPrompts(serverName: string): Promise<MCPPrompt[]>;
+  getPrompt(serverName: string, promptName: string, args?: Record<string, string>): Promise<MCPGetPromptResult>;
+  completeArgument(serverName: string, reference: MCPCompletionReference, argumentName: string, value: string, context?: Record<string, string>): Promise<MCPCompletion>;
+  onNotification(event: MCPNotificationEventName, handler: (event: MCPNotificationEvent) => void): Promise<UnlistenFn>;
+  onToolProgress(callId: string, handler: (event: MCPProgressEvent) => void): Promise<UnlistenFn>;
+  onServerStatus(handler: (event: MCPServerStatusEvent) => void): Promise<UnlistenFn>;
+  onSamplingApproval(handler: (event: MCPSamplingRequestEvent) => void): Promise<UnlistenFn>;
+  onSamplingRequest(handler: (event: MCPSamplingRequestEvent) => void): Promise<UnlistenFn>;
+  respondSamplingApproval(requestId: string, approved: boolean): Promise<void>;
+  respondSampling(requestId: string, result: MCPSamplingResult | null, error?: string): Promise<void>;
+  onElicitationRequest(handler: (event: MCPElicitationRequestEvent) => void): Promise<UnlistenFn>;
+  respondElicitation(requestId: string, action: MCPElicitationAction, content?: Record<string, any>): Promise<void>;
+  onHostRequestWithdrawn(handler: (event: MCPHostRequestWithdrawnEvent) => void): Promise<UnlistenFn>;
+  setWorkspaceRoots(paths: string[]): Promise<void>;
 }
 
 export class TauriMCPServiceImpl implements TauriMCPService {
@@ -23,22 +48,38 @@ export class TauriMCPServiceImpl implements TauriMCPService {
       return result;
     } catch (error) {
       console.error(`Failed to connect MCP server ${serverName}:`, error);
-      throw new Error(`Failed to connect server: ${error}`);
+      throw toCommandError(error);
     }
   }
 
-  async connectServerWithEnv(serverName: string, command: string, args: string[], env: Record<string, string>): Promise<string> {
+  async connectServerWithEnv(serverName: string, command: string, args: string[], env: Record<string, string>, options?: MCPClientOptions): Promise<string> {
     try {
       const result = await invoke<string>('connect_mcp_server', {
         serverName,
         command,
         args,
-        env
+        env,
+        options
       });
       return result;
     } catch (error) {
       console.error(`Failed to connect MCP server ${serverName} with env:`, error);
-      throw new Error(`Failed to connect server: ${error}`);
+      throw toCommandError(error);
+    }
+  }
+
+  async connectServerHttp(serverName: string, url: string, headers: Record<string, string>, options?: MCPClientOptions): Promise<string> {
+    try {
+      const result = await invoke<string>('connect_mcp_server_http', {
+        serverName,
+        url,
+        headers,
+        options
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to connect remote MCP server ${serverName}:`, error);
+      throw toCommandError(error);
     }
   }
 
@@ -50,69 +91,282 @@ export class TauriMCPServiceImpl implements TauriMCPService {
       return result;
     } catch (error) {
       console.error(`Failed to disconnect MCP server ${serverName}:`, error);
-      throw new Error(`Failed to disconnect server: ${error}`);
+      throw toCommandError(error);
     }
   }
 
-  async listConnectedServers(): Promise<string[]> {
+  async listConnectedServers(): Promise<MCPConnectedServer[]> {
     try {
-      const result = await invoke<string[]>('list_connected_servers');
+      const result = await invoke<MCPConnectedServer[]>('list_connected_servers');
       return result;
     } catch (error) {
       console.error('Failed to list connected MCP servers:', error);
-      throw new Error(`Failed to list servers: ${error}`);
+      throw toCommandError(error);
+    }
+  }
+
+  async getServerInfo(serverName: string): Promise<MCPServerInfo> {
+    try {
+      const result = await invoke<MCPServerInfo>('get_mcp_server_info', {
+        serverName
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to get info for MCP server ${serverName}:`, error);
+      throw toCommandError(error);
     }
   }
 
-  async listTools(serverName: string): Promise<any> {
+  async getServerLogs(serverName: string, limit?: number): Promise<MCPLogEntry[]> {
     try {
-      const result = await invoke<any>('list_mcp_tools', {
+      const result = await invoke<MCPLogEntry[]>('get_mcp_server_logs', {
+        serverName,
+        limit
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to get logs for MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async setLogLevel(serverName: string, level: MCPLogLevel): Promise<void> {
+    try {
+      await invoke('set_mcp_log_level', {
+        serverName,
+        level
+      });
+    } catch (error) {
+      console.error(`Failed to set log level for MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async listTools(serverName: string): Promise<MCPToolDefinition[]> {
+    try {
+      const result = await invoke<MCPToolDefinition[]>('list_mcp_tools', {
         serverName
       });
       return result;
     } catch (error) {
       console.error(`Failed to list tools from MCP server ${serverName}:`, error);
-      throw new Error(`Failed to list tools: ${error}`);
+      throw toCommandError(error);
     }
   }
 
-  async callTool(serverName: string, toolName: string, args: any): Promise<any> {
+  async callTool(serverName: string, toolName: string, args: any, options?: MCPToolCallOptions): Promise<MCPCallToolResult> {
     try {
-      const result = await invoke<any>('call_mcp_tool', {
+      const result = await invoke<MCPCallToolResult>('call_mcp_tool', {
         serverName,
         toolName,
-        arguments: args
+        arguments: args,
+        timeoutMs: options?.timeoutMs,
+        callId: options?.callId
       });
       
       return result;
     } catch (error) {
       console.error(`Failed to call tool ${toolName} on MCP server ${serverName}:`, error);
-      throw new Error(`Failed to call tool: ${error}`);
+      throw toCommandError(error);
     }
   }
 
-  async listResources(serverName: string): Promise<any> {
+  async cancelToolCall(serverName: string, callId: string, reason?: string): Promise<string> {
     try {
-      const result = await invoke<any>('list_mcp_resources', {
+      const result = await invoke<string>('cancel_mcp_tool_call', {
+        serverName,
+        callId,
+        reason
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to cancel tool call ${callId} on MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async listResources(serverName: string): Promise<MCPResource[]> {
+    try {
+      const result = await invoke<MCPResource[]>('list_mcp_resources', {
         serverName
       });
       return result;
     } catch (error) {
       console.error(`Failed to list resources from MCP server ${serverName}:`, error);
-      throw new Error(`Failed to list resources: ${error}`);
+      throw toCommandError(error);
     }
   }
 
-  async readResource(serverName: string, uri: string): Promise<any> {
+  async readResource(serverName: string, uri: string): Promise<MCPReadResourceResult> {
     try {
-      const result = await invoke<any>('read_mcp_resource', {
+      const result = await invoke<MCPReadResourceResult>('read_mcp_resource', {
         serverName,
         uri
       });
       return result;
     } catch (error) {
       console.error(`Failed to read resource ${uri} from MCP server ${serverName}:`, error);
-      throw new Error(`Failed to read resource: ${error}`);
+      throw toCommandError(error);
+    }
+  }
+
+  async listResourceTemplates(serverName: string): Promise<MCPResourceTemplate[]> {
+    try {
+      const result = await invoke<MCPResourceTemplate[]>('list_mcp_resource_templates', {
+        serverName
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to list resource templates from MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async subscribeResource(serverName: string, uri: string): Promise<void> {
+    try {
+      await invoke('subscribe_mcp_resource', {
+        serverName,
+        uri
+      });
+    } catch (error) {
+      console.error(`Failed to subscribe to resource ${uri} on MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async unsubscribeResource(serverName: string, uri: string): Promise<void> {
+    try {
+      await invoke('unsubscribe_mcp_resource', {
+        serverName,
+        uri
+      });
+    } catch (error) {
+      console.error(`Failed to unsubscribe from resource ${uri} on MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async listPrompts(serverName: string): Promise<MCPPrompt[]> {
+    try {
+      const result = await invoke<MCPPrompt[]>('list_mcp_prompts', {
+        serverName
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to list prompts from MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async getPrompt(serverName: string, promptName: string, args?: Record<string, string>): Promise<MCPGetPromptResult> {
+    try {
+      const result = await invoke<MCPGetPromptResult>('get_mcp_prompt', {
+        serverName,
+        promptName,
+        arguments: args
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to get prompt ${promptName} from MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async completeArgument(serverName: string, reference: MCPCompletionReference, argumentName: string, value: string, context?: Record<string, string>): Promise<MCPCompletion> {
+    try {
+      const result = await invoke<MCPCompletion>('complete_mcp_argument', {
+        serverName,
+        reference,
+        argumentName,
+        value,
+        context
+      });
+      return result;
+    } catch (error) {
+      console.error(`Failed to complete argument ${argumentName} on MCP server ${serverName}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async onNotification(event: MCPNotificationEventName, handler: (event: MCPNotificationEvent) => void): Promise<UnlistenFn> {
+    return listen<MCPNotificationEvent>(event, (e) => handler(e.payload));
+  }
+
+  async onToolProgress(callId: string, handler: (event: MCPProgressEvent) => void): Promise<UnlistenFn> {
+    return listen<MCPProgressEvent>('mcp:progress', (e) => {
+      if (e.payload.call_id === callId) {
+        handler(e.payload);
+      }
+    });
+  }
+
+  async onServerStatus(handler: (event: MCPServerStatusEvent) => void): Promise<UnlistenFn> {
+    return listen<MCPServerStatusEvent>('mcp:server-status', (e) => handler(e.payload));
+  }
+
+  async onSamplingApproval(handler: (event: MCPSamplingRequestEvent) => void): Promise<UnlistenFn> {
+    return listen<MCPSamplingRequestEvent>('mcp:sampling-approval', (e) => handler(e.payload));
+  }
+
+  async onSamplingRequest(handler: (event: MCPSamplingRequestEvent) => void): Promise<UnlistenFn> {
+    return listen<MCPSamplingRequestEvent>('mcp:sampling-request', (e) => handler(e.payload));
+  }
+
+  async respondSamplingApproval(requestId: string, approved: boolean): Promise<void> {
+    try {
+      await invoke('respond_mcp_sampling_approval', {
+        requestId,
+        approved
+      });
+    } catch (error) {
+      console.error(`Failed to answer sampling approval ${requestId}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  async respondSampling(requestId: string, result: MCPSamplingResult | null, error?: string): Promise<void> {
+    try {
+      await invoke('respond_mcp_sampling', {
+        requestId,
+        result,
+        error
+      });
+    } catch (err) {
+      console.error(`Failed to answer sampling request ${requestId}:`, err);
+      throw toCommandError(err);
+    }
+  }
+
+  async onElicitationRequest(handler: (event: MCPElicitationRequestEvent) => void): Promise<UnlistenFn> {
+    return listen<MCPElicitationRequestEvent>('mcp:elicitation-request', (e) => handler(e.payload));
+  }
+
+  async respondElicitation(requestId: string, action: MCPElicitationAction, content?: Record<string, any>): Promise<void> {
+    try {
+      await invoke('respond_mcp_elicitation', {
+        requestId,
+        action,
+        content
+      });
+    } catch (error) {
+      console.error(`Failed to answer elicitation ${requestId}:`, error);
+      throw toCommandError(error);
+    }
+  }
+
+  // A sampling or elicitation prompt for this request should be closed; answering it now fails
+  async onHostRequestWithdrawn(handler: (event: MCPHostRequestWithdrawnEvent) => void): Promise<UnlistenFn> {
+    return listen<MCPHostRequestWithdrawnEvent>('mcp:host-request-withdrawn', (e) => handler(e.payload));
+  }
+
+  async setWorkspaceRoots(paths: string[]): Promise<void> {
+    try {
+      await invoke('set_mcp_workspace_roots', {
+        paths
+      });
+    } catch (error) {
+      console.error('Failed to set MCP workspace roots:', error);
+      throw toCommandError(error);
     }
   }
 
@@ -126,7 +380,8 @@ export class TauriMCPServiceImpl implements TauriMCPService {
   }
 
   async listServers(): Promise<string[]> {
-    return this.listConnectedServers();
+    const servers = await this.listConnectedServers();
+    return servers.map(server => server.server_name);
   }
 
   async sendMessage(serverName: string, message: any): Promise<any> {
@@ -139,6 +394,10 @@ export class TauriMCPServiceImpl implements TauriMCPService {
       return this.listResources(serverName);
     } else if (message.method === 'resources/read') {
       return this.readResource(serverName, message.params.uri);
+    } else if (message.method === 'prompts/list') {
+      return this.listPrompts(serverName);
+    } else if (message.method === 'prompts/get') {
+      return this.getPrompt(serverName, message.params.name, message.params.arguments);
     } else {
       throw new Error(`Unsupported message method: ${message.method}`);
     }
@@ -1,10 +1,76 @@
+import { CommandErrorPayload } from '../utils/commandError';
+
 export interface MCPServerConfig {
   name: string;
+  // Executable to spawn, or tcp://host:port / unix:///path for a running server
   command: string;
   args: string[];
   env?: Record<string, string>;
   description: string;
   category: 'core' | 'filesystem' | 'database' | 'search' | 'git' | 'web3' | 'custom' | 'conversational' | 'development';
+  restart?: MCPRestartOptions;
+  limits?: MCPResourceLimits;
+  // Let the server run completions on the app's model, approved by the user each time; on by default
+  sampling?: boolean;
+  // Let the server ask the user for input while a call runs; on by default
+  elicitation?: boolean;
+  // Share the workspace folder with the server; on by default for filesystem servers
+  roots?: boolean;
+}
+
+// Process limits apply to local servers on Linux only; the call cap applies everywhere
+export interface MCPResourceLimits {
+  maxMemoryBytes?: number;
+  maxCpuSeconds?: number;
+  maxOpenFiles?: number;
+  // At least 1; calls beyond it queue and can still be cancelled or time out
+  maxConcurrentCalls?: number;
+}
+
+export type MCPResourceLimit = 'memory' | 'cpu_time' | 'open_files';
+
+export type MCPRestartPolicy = 'never' | 'on-failure' | 'always';
+
+// When a local server that exits on its own is started again; backoff doubles per attempt
+export interface MCPRestartOptions {
+  policy?: MCPRestartPolicy;
+  maxRetries?: number;
+  initialBackoffMs?: number;
+  maxBackoffMs?: number;
+}
+
+// Per-server options passed to connect_mcp_server
+export interface MCPClientOptions {
+  // Deadline for requests other than tool calls; defaults to 60000
+  requestTimeoutMs?: number;
+  // Default deadline for tool calls; defaults to 60000, 0 lets them wait as long as the server takes
+  toolTimeoutMs?: number;
+  // Answer sampling/createMessage via the mcp:sampling-* events
+  sampling?: boolean;
+  // Answer elicitation/create via the mcp:elicitation-request event
+  elicitation?: boolean;
+  // Share the workspace roots set with setWorkspaceRoots
+  roots?: boolean;
+  logLevel?: MCPLogLevel;
+  // Fail tool calls whose structuredContent does not match the tool's outputSchema
+  validateOutput?: boolean;
+  restart?: MCPRestartOptions;
+  // Ping interval for health checks; defaults to 30000, 0 turns checks off
+  healthCheckIntervalMs?: number;
+  limits?: MCPResourceLimits;
+}
+
+// data of an invalid_arguments or invalid_output command error
+export interface MCPValidationError {
+  path: string;
+  expected: string;
+  found: string;
+}
+
+export interface MCPToolCallOptions {
+  timeoutMs?: number;
+  // Also the progress token; mcp:progress events for the call carry it as call_id
+  callId?: string;
 }
 
 export interface MCPServerInstance {
@@ -25,11 +91,216 @@ export interface MCPTool {
 export interface MCPResource {
   uri: string;
   name: string;
+  title?: string;
+  description?: string;
+  mimeType?: string;
+  size?: number;
+  annotations?: any;
+}
+
+// A tool exactly as list_mcp_tools returns it
+export interface MCPToolDefinition {
+  name: string;
+  title?: string;
+  description?: string;
+  inputSchema: any;
+  outputSchema?: any;
+  annotations?: any;
+}
+
+export interface MCPResourceTemplate {
+  uriTemplate: string;
+  name: string;
+  title?: string;
   description?: string;
   mimeType?: string;
+  annotations?: any;
+}
+
+// Text or base64-encoded binary contents of a resource
+export type MCPResourceContents =
+  | { uri: string; mimeType?: string; text: string }
+  | { uri: string; mimeType?: string; blob: string };
+
+export type MCPContentBlock =
+  | { type: 'text'; text: string; annotations?: any }
+  | { type: 'image'; data: string; mimeType: string; annotations?: any }
+  | { type: 'audio'; data: string; mimeType: string; annotations?: any }
+  | ({ type: 'resource_link' } & MCPResource)
+  | { type: 'resource'; resource: MCPResourceContents; annotations?: any };
+
+export interface MCPCallToolResult {
+  content: MCPContentBlock[];
+  structuredContent?: any;
+  // The tool itself failed; the content describes the failure
+  isError: boolean;
+}
+
+export interface MCPReadResourceResult {
+  contents: MCPResourceContents[];
+}
+
+export interface MCPPromptArgument {
+  name: string;
+  title?: string;
+  description?: string;
+  required: boolean;
+}
+
+export interface MCPPrompt {
+  name: string;
+  title?: string;
+  description?: string;
+  arguments: MCPPromptArgument[];
+}
+
+// The prompt or resource template whose argument complete_mcp_argument completes
+export type MCPCompletionReference =
+  | { type: 'ref/prompt'; name: string }
+  | { type: 'ref/resource'; uri: string };
+
+export interface MCPCompletion {
+  values: string[];
+  total?: number;
+  hasMore: boolean;
+}
+
+export interface MCPGetPromptResult {
+  description?: string;
+  messages: { role: string; content: MCPContentBlock }[];
+}
+
+// Result of initialize as reported by get_mcp_server_info
+export interface MCPServerInfo {
+  protocolVersion: string;
+  capabilities: {
+    tools?: any;
+    resources?: { subscribe?: boolean; listChanged?: boolean };
+    prompts?: any;
+    logging?: any;
+    completions?: any;
+    experimental?: any;
+  };
+  serverInfo: {
+    name: string;
+    version: string;
+    title?: string;
+  };
+  instructions?: string;
+}
+
+export type MCPHealthState = 'healthy' | 'degraded' | 'unresponsive';
+
+// Outcome of recent health-check pings; mcp:health carries the new value on each change
+export interface MCPServerHealth {
+  state: MCPHealthState;
+  interval_ms?: number;
+  latency_ms?: number;
+  last_checked?: number;
+  consecutive_failures: number;
+  last_error?: string;
+}
+
+export interface MCPConnectedServer extends MCPServerHealth {
+  server_name: string;
+}
+
+// Payload of mcp:server-status, emitted as a supervised server exits and restarts
+export interface MCPServerStatusEvent {
+  server_name: string;
+  // stopped means no more restarts; the server is no longer connected
+  status: 'exited' | 'restarting' | 'running' | 'stopped';
+  exit_code?: number;
+  stderr?: string;
+  // The resource limit the process ran into, when that is what stopped it
+  limit?: MCPResourceLimit;
+  attempt?: number;
+  error?: CommandErrorPayload;
 }
 
 export interface MCPServerEvent {
   type: string;
   data: any;
 }
+
+export type MCPNotificationEventName =
+  | 'mcp:tools-list-changed'
+  | 'mcp:resources-list-changed'
+  | 'mcp:prompts-list-changed'
+  | 'mcp:resource-updated'
+  | 'mcp:message'
+  | 'mcp:progress'
+  | 'mcp:stderr'
+  | 'mcp:health'
+  | 'mcp:notification';
+
+// Payload of the Tauri events re-emitted from server notifications
+export interface MCPNotificationEvent {
+  server_name: string;
+  call_id?: string;
+  kind: string;
+  [key: string]: any;
+}
+
+// Payload of mcp:progress
+export interface MCPProgressEvent extends MCPNotificationEvent {
+  kind: 'progress';
+  progressToken: string | number;
+  progress: number;
+  total?: number;
+  message?: string;
+}
+
+export interface MCPSamplingMessage {
+  role: 'user' | 'assistant';
+  content: any;
+}
+
+// Payload of mcp:sampling-approval and mcp:sampling-request
+export interface MCPSamplingRequestEvent {
+  request_id: string;
+  server_name: string;
+  messages: MCPSamplingMessage[];
+  modelPreferences?: any;
+  systemPrompt?: string;
+  includeContext?: 'none' | 'thisServer' | 'allServers';
+  temperature?: number;
+  maxTokens: number;
+  stopSequences?: string[];
+  metadata?: any;
+}
+
+export interface MCPSamplingResult {
+  role: 'user' | 'assistant';
+  content: any;
+  model: string;
+  stopReason?: string;
+}
+
+// Payload of mcp:elicitation-request
+export interface MCPElicitationRequestEvent {
+  request_id: string;
+  server_name: string;
+  message: string;
+  requestedSchema: any;
+}
+
+export type MCPElicitationAction = 'accept' | 'decline' | 'cancel';
+
+// Payload of mcp:host-request-withdrawn: a sampling or elicitation request
+// that timed out, or whose tool call ended, before it was answered
+export interface MCPHostRequestWithdrawnEvent {
+  request_id: string;
+  server_name: string;
+}
+
+export type MCPLogLevel = 'debug' | 'info' | 'notice' | 'warning' | 'error' | 'critical' | 'alert' | 'emergency';
+
+// An entry from get_mcp_server_logs
+export interface MCPLogEntry {
+  timestamp: number;
+  source: 'server' | 'stderr';
+  level: MCPLogLevel;
+  logger?: string;
+  data: any;
+}
@@ -0,0 +1,38 @@
+// Error payload returned by every Tauri command
+export interface CommandErrorPayload {
+  // e.g. not_connected, rpc_error, timeout, cancelled, server_exited, io_error
+  code: string;
+  message: string;
+  // JSON-RPC error code when the server answered with an error
+  rpc_code?: number;
+  // JSON-RPC error data, or details such as exit_code and stderr
+  data?: any;
+  server_name?: string;
+}
+
+export class CommandError extends Error {
+  code: string;
+  rpcCode?: number;
+  data?: any;
+  serverName?: string;
+
+  constructor(payload: CommandErrorPayload) {
+    super(payload.message);
+    this.name = 'CommandError';
+    this.code = payload.code;
+    this.rpcCode = payload.rpc_code;
+    this.data = payload.data;
+    this.serverName = payload.server_name;
+  }
+}
+
+// Turns whatever a failed invoke() rejected with into a CommandError
+export function toCommandError(error: unknown): CommandError {
+  if (error instanceof CommandError) {
+    return error;
+  }
+  if (error && typeof error === 'object' && 'code' in error && 'message' in error) {
+    return new CommandError(error as CommandErrorPayload);
+  }
+  return new CommandError({ code: 'unknown', message: String(error) });
+}